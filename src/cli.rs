@@ -0,0 +1,685 @@
+use std::{
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    config::Config,
+    filesystem::{filter_files, list_files, DEFAULT_OUT_DIR, DEFAULT_REGEX},
+    imagemagick::{JpegColorspace, OutputFormat, PngMode, Subsampling},
+    pipeline::{
+        Backup, CancellationToken, Collision, Event, LargerOutputPolicy, Options, Outcome,
+        Pipeline, Shrinked, Srcset,
+    },
+    preset::Preset,
+    progress::{format_duration, Progress},
+    report::{FileReport, Report, ReportFormat},
+    template,
+    threadpool::ThreadPool,
+    utils::{round_percent, Dimensions, ResizeMode},
+    watch::{Watcher, POLL_INTERVAL},
+};
+
+#[derive(Parser)]
+#[clap(
+    name = "rshrink",
+    version,
+    about = "Minimize image and video file sizes"
+)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open the graphical user interface (default)
+    Gui,
+    /// Shrink all matching files of a directory without a display
+    Shrink(ShrinkArgs),
+}
+
+#[derive(Args)]
+pub struct ShrinkArgs {
+    /// Regular expression the file names have to match [default: images and videos]
+    pub file_regex: Option<String>,
+    /// Directory to read the files from
+    #[clap(default_value = ".")]
+    pub in_dir: String,
+    /// Directory to write the shrinked files to [default: _rshrinked]
+    pub out_dir: Option<String>,
+    /// Scale images and videos to WIDTHxHEIGHT according to --resize
+    #[clap(short, long, parse(try_from_str = Dimensions::parse_dimensions))]
+    pub dimensions: Option<Dimensions>,
+    /// How --dimensions are applied: shrink (never enlarge, default), fit, fill (crop the overlap)
+    /// or exact
+    #[clap(long)]
+    pub resize: Option<ResizeMode>,
+    /// Scale images and videos by this percentage instead of --dimensions
+    #[clap(
+        long,
+        conflicts_with_all = &["dimensions", "long_edge"],
+        parse(try_from_str = parse_percent)
+    )]
+    pub percent: Option<Dimensions>,
+    /// Shrink the longer side of images and videos to this many pixels instead of --dimensions
+    #[clap(long, conflicts_with = "dimensions", parse(try_from_str = parse_long_edge))]
+    pub long_edge: Option<Dimensions>,
+    /// Write every image in each of these sizes, e.g. 320,640,1280,1920 (widths) or 800x600.
+    /// Named "{stem}-{width}w.{ext}" unless --name is given
+    #[clap(
+        long,
+        use_value_delimiter = true,
+        parse(try_from_str = Dimensions::parse_size)
+    )]
+    pub sizes: Vec<Dimensions>,
+    /// Describe the sizes of every image in an html srcset snippet or a json file: none, html or
+    /// json [default: none]
+    #[clap(long, requires = "sizes")]
+    pub srcset: Option<Srcset>,
+    /// Apply a slight gaussian blur (slow)
+    #[clap(short, long = "gaussian_blur")]
+    pub gaussian_blur: bool,
+    /// Compression quality (1-100) [default: 85]
+    #[clap(short, long, parse(try_from_str = parse_quality))]
+    pub quality: Option<usize>,
+    /// Search the highest quality that keeps each image below this size in Kb, overrides --quality
+    #[clap(short, long, parse(try_from_str = parse_target_size))]
+    pub target_size: Option<u64>,
+    /// Reduce the dimensions if an image can't reach --target-size otherwise
+    #[clap(short = 'r', long, requires = "target_size")]
+    pub allow_resize: bool,
+    /// Print SSIM and PSNR of every shrinked image compared with the original
+    #[clap(long)]
+    pub metrics: bool,
+    /// Search the lowest quality whose SSIM (0-1) reaches this, overrides --quality
+    #[clap(long, conflicts_with = "target_size", parse(try_from_str = parse_ssim))]
+    pub min_ssim: Option<f64>,
+    /// Convert images to original, jpeg, png, webp, avif or jxl [default: original]
+    #[clap(short, long)]
+    pub format: Option<OutputFormat>,
+    /// Color transparent areas are flattened against when converting to jpeg, as RRGGBB
+    /// [default: ffffff]
+    #[clap(short, long, parse(try_from_str = parse_color))]
+    pub background: Option<[u8; 3]>,
+    /// Encode webp losslessly
+    #[clap(long)]
+    pub webp_lossless: bool,
+    /// AVIF encoder speed from 0 (smallest) to 10 (fastest) [default: 6]
+    #[clap(long)]
+    pub avif_speed: Option<u8>,
+    /// JPEG XL encoder effort from 1 (fastest) to 9 (smallest) [default: 7]
+    #[clap(long)]
+    pub jxl_effort: Option<u8>,
+    /// How PNGs are compressed: lossless, auto (quantized unless they have few colors) or
+    /// quantize [default: lossless]
+    #[clap(long)]
+    pub png_mode: Option<PngMode>,
+    /// Palette size of quantized PNGs from 2 to 256 [default: 256]
+    #[clap(long)]
+    pub png_colors: Option<usize>,
+    /// Don't dither quantized PNGs
+    #[clap(long)]
+    pub no_dither: bool,
+    /// zlib level of PNGs from 0 (fastest) to 9 (smallest) [default: 9]
+    #[clap(long)]
+    pub png_level: Option<u8>,
+    /// Keep 16 bits per channel in quantized PNGs
+    #[clap(long)]
+    pub keep_depth: bool,
+    /// Chroma subsampling of JPEGs: 444, 422 or 420 [default: 420]
+    #[clap(long)]
+    pub subsampling: Option<Subsampling>,
+    /// Write baseline instead of progressive JPEGs
+    #[clap(long)]
+    pub baseline: bool,
+    /// Use the standard Huffman tables for JPEGs instead of computing optimal ones
+    #[clap(long)]
+    pub no_optimize_coding: bool,
+    /// Use arithmetic coding for JPEGs, smaller but not supported by most browsers
+    #[clap(long)]
+    pub arithmetic_coding: bool,
+    /// Convert JPEGs to this colorspace: keep, srgb or gray [default: keep]
+    #[clap(long)]
+    pub colorspace: Option<JpegColorspace>,
+    /// Don't rotate images according to their EXIF orientation
+    #[clap(long)]
+    pub no_auto_orient: bool,
+    /// Only remove the GPS location and keep all other metadata
+    #[clap(long)]
+    pub strip_gps_only: bool,
+    /// Remove color profiles as well
+    #[clap(long)]
+    pub strip_icc: bool,
+    /// Don't convert images to sRGB when their color profile is removed
+    #[clap(long, requires = "strip_icc")]
+    pub no_srgb: bool,
+    /// Keep the EXIF artist and copyright
+    #[clap(long)]
+    pub keep_copyright: bool,
+    /// What to do if a file ends up larger than its original: copy, skip or retry [default: copy]
+    #[clap(long)]
+    pub if_larger: Option<LargerOutputPolicy>,
+    /// Path of the output files inside OUT_DIR, e.g. "{stem}-{width}w.{ext}" or "{date}/{name}".
+    /// Placeholders: name, stem, ext, width, height, quality, preset, index, date
+    #[clap(long, parse(try_from_str = parse_template))]
+    pub name: Option<String>,
+    /// What to do if an output file already exists: overwrite, skip or counter [default: overwrite]
+    #[clap(long)]
+    pub if_exists: Option<Collision>,
+    /// Replace the originals with their shrinked version instead of writing into OUT_DIR
+    #[clap(long, conflicts_with_all = &["out_dir", "sizes", "name"])]
+    pub in_place: bool,
+    /// Keep replaced originals next to them with a .bak suffix
+    #[clap(long, requires = "in_place")]
+    pub backup: bool,
+    /// Move replaced originals into this directory, mirroring their directory structure
+    #[clap(long, requires = "in_place", conflicts_with = "backup")]
+    pub backup_dir: Option<String>,
+    /// Include subdirectories, their structure is recreated inside OUT_DIR
+    #[clap(short = 'R', long)]
+    pub recursive: bool,
+    /// Keep running and shrink new files of IN_DIR as they appear, until Ctrl+C
+    #[clap(short, long)]
+    pub watch: bool,
+    /// Shrink all files again, even if they didn't change since the last run
+    #[clap(long)]
+    pub force: bool,
+    /// Write sizes, dimensions, quality, time and errors of every file to this path, as csv,
+    /// json or html depending on its extension
+    #[clap(long)]
+    pub report: Option<String>,
+    /// Format of --report if its extension doesn't tell: csv, json or html
+    #[clap(long, requires = "report")]
+    pub report_format: Option<ReportFormat>,
+    /// Use a preset of the project config instead of its defaults
+    #[clap(short, long)]
+    pub preset: Option<String>,
+    /// Ignore rshrink.toml and .rshrink.json in IN_DIR and its parents
+    #[clap(long, conflicts_with = "preset")]
+    pub no_config: bool,
+    /// Number of worker threads, defaults to the number of cpus
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+}
+
+fn parse_quality(quality: &str) -> Result<usize, String> {
+    match quality.parse::<usize>() {
+        Ok(quality) if (1..=100).contains(&quality) => Ok(quality),
+        _ => Err(format!(
+            "Quality has to be between 1 and 100, got {quality}"
+        )),
+    }
+}
+
+fn parse_ssim(ssim: &str) -> Result<f64, String> {
+    match ssim.parse::<f64>() {
+        Ok(ssim) if (0.0..=1.0).contains(&ssim) => Ok(ssim),
+        _ => Err(format!("SSIM has to be between 0 and 1, got {ssim}")),
+    }
+}
+
+/// Size in Kb, converted to bytes
+fn parse_target_size(target_size: &str) -> Result<u64, String> {
+    match target_size
+        .parse::<u64>()
+        .ok()
+        .and_then(|kb| kb.checked_mul(1024))
+    {
+        Some(size) if size > 0 => Ok(size),
+        _ => Err(format!(
+            "Target size has to be a positive number of Kb, got {target_size}"
+        )),
+    }
+}
+
+fn parse_template(template: &str) -> Result<String, String> {
+    template::validate(template)
+        .map(|_| template.to_owned())
+        .map_err(|err| err.to_string())
+}
+
+fn parse_percent(percent: &str) -> Result<Dimensions, String> {
+    let percent = percent.parse::<usize>().map_err(|err| err.to_string())?;
+    Dimensions::percent(percent).map_err(|err| err.to_string())
+}
+
+fn parse_long_edge(long_edge: &str) -> Result<Dimensions, String> {
+    let long_edge = long_edge.parse::<usize>().map_err(|err| err.to_string())?;
+    Dimensions::long_edge(long_edge).map_err(|err| err.to_string())
+}
+
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("Expected a color like ffffff, got {color}")),
+    }
+}
+
+/// Options given on the command line, everything else is taken from `base` or the built in
+/// defaults
+fn options(args: &ShrinkArgs, base: Option<&Preset>) -> Options {
+    let mut options = match base {
+        Some(preset) => preset.options(),
+        // Without a config everything is written into a single folder
+        None => Options {
+            output_folder_name: String::new(),
+            output_folder_parent_dir: Some(PathBuf::from(DEFAULT_OUT_DIR)),
+            ..Options::default()
+        },
+    };
+    match (&args.dimensions, &args.percent, &args.long_edge) {
+        (Some(dimensions), _, _) => {
+            options.dimensions = Some(Dimensions {
+                mode: args.resize.unwrap_or_default(),
+                ..dimensions.clone()
+            })
+        }
+        (_, Some(dimensions), _) | (_, _, Some(dimensions)) => {
+            options.dimensions = Some(dimensions.clone())
+        }
+        _ => {
+            if let (Some(dimensions), Some(mode)) = (&mut options.dimensions, args.resize) {
+                dimensions.mode = mode;
+            }
+        }
+    }
+    if !args.sizes.is_empty() {
+        options.variants = args.sizes.clone();
+    }
+    if let Some(srcset) = args.srcset {
+        options.srcset = srcset;
+    }
+    if let Some(quality) = args.quality {
+        options.compression_quality = quality;
+    }
+    options.apply_gaussian_blur |= args.gaussian_blur;
+    if let Some(target_size) = args.target_size {
+        options.target_size = Some(target_size);
+        options.min_ssim = None;
+    }
+    options.target_size_resize |= args.allow_resize;
+    options.metrics |= args.metrics;
+    if let Some(min_ssim) = args.min_ssim {
+        options.min_ssim = Some(min_ssim);
+        options.target_size = None;
+    }
+
+    let format = &mut options.format;
+    if let Some(output_format) = args.format {
+        format.format = output_format;
+    }
+    if let Some(background) = args.background {
+        format.background = background;
+    }
+    format.webp_lossless |= args.webp_lossless;
+    if let Some(avif_speed) = args.avif_speed {
+        format.avif_speed = avif_speed;
+    }
+    if let Some(jxl_effort) = args.jxl_effort {
+        format.jxl_effort = jxl_effort;
+    }
+    if let Some(png_mode) = args.png_mode {
+        format.png_mode = png_mode;
+    }
+    if let Some(png_colors) = args.png_colors {
+        format.png_colors = png_colors;
+    }
+    format.png_dither &= !args.no_dither;
+    if let Some(png_level) = args.png_level {
+        format.png_compression_level = png_level;
+    }
+    format.png_reduce_depth &= !args.keep_depth;
+    if let Some(subsampling) = args.subsampling {
+        format.jpeg_subsampling = subsampling;
+    }
+    format.jpeg_progressive &= !args.baseline;
+    format.jpeg_optimize_coding &= !args.no_optimize_coding;
+    format.jpeg_arithmetic_coding |= args.arithmetic_coding;
+    if let Some(colorspace) = args.colorspace {
+        format.jpeg_colorspace = colorspace;
+    }
+
+    let metadata = &mut options.metadata;
+    metadata.auto_orient &= !args.no_auto_orient;
+    metadata.strip_gps_only |= args.strip_gps_only;
+    metadata.keep_icc &= !args.strip_icc;
+    metadata.convert_to_srgb &= !args.no_srgb;
+    metadata.keep_copyright |= args.keep_copyright;
+
+    if let Some(larger_output) = args.if_larger {
+        options.larger_output = larger_output;
+    }
+    if let Some(template) = &args.name {
+        options.file_name_template = Some(template.clone());
+    }
+    if let Some(collision) = args.if_exists {
+        options.collision = collision;
+    }
+    options.preset_name = args.preset.clone();
+    options.replace_originals |= args.in_place;
+    if args.backup {
+        options.backup = Backup::Suffix;
+    }
+    if let Some(backup_dir) = &args.backup_dir {
+        options.backup = Backup::Dir;
+        options.backup_dir = Some(PathBuf::from(backup_dir));
+    }
+    options.incremental &= !args.force;
+    if let Some(out_dir) = &args.out_dir {
+        options.output_folder_name = String::new();
+        options.output_folder_parent_dir = Some(PathBuf::from(out_dir));
+    }
+    options
+}
+
+/// Shrink all files of `args.in_dir` matching the file regex, returns the process exit code
+pub fn shrink(args: ShrinkArgs) -> i32 {
+    let config = match args.no_config {
+        true => None,
+        false => match Config::discover(Path::new(&args.in_dir)) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Failed to read config! {err}");
+                return 1;
+            }
+        },
+    };
+    if let Some((path, _)) = &config {
+        println!("Using config {}", path.display());
+    }
+    let config = config.map(|(_, config)| config);
+    let base = match (&config, &args.preset) {
+        (Some(config), preset) => match config.preset(preset.as_deref()) {
+            Some(base) => Some(base),
+            None => {
+                eprintln!(
+                    "Preset {} not found in config!",
+                    preset.as_deref().unwrap_or("")
+                );
+                return 1;
+            }
+        },
+        (None, Some(preset)) => {
+            eprintln!("No config found for preset {preset}!");
+            return 1;
+        }
+        (None, None) => None,
+    };
+    let file_regex = args
+        .file_regex
+        .clone()
+        .or_else(|| config.as_ref().and_then(|config| config.file_regex.clone()))
+        .unwrap_or_else(|| String::from(DEFAULT_REGEX));
+    let recursive = args.recursive || config.as_ref().map_or(false, |config| config.recursive);
+    let options = options(&args, base);
+    // A template from a config file isn't checked while the arguments are parsed
+    if let Err(err) = options.validate() {
+        eprintln!("{err}");
+        return 1;
+    }
+
+    let pipeline = match recursive {
+        true => Pipeline::from_dir(Path::new(&args.in_dir), &file_regex, options),
+        false => list_files(&args.in_dir)
+            .and_then(|files| filter_files(files, &file_regex))
+            .and_then(|files| {
+                Pipeline::new(
+                    files.iter().map(|file| Path::new(&args.in_dir).join(file)),
+                    options,
+                )
+            }),
+    };
+    let pipeline = match pipeline {
+        Ok(pipeline) => pipeline,
+        Err(err) => {
+            eprintln!("Failed to read directory! {err}");
+            return 1;
+        }
+    };
+    if pipeline.jobs().is_empty() && !args.watch {
+        println!("No files matching {file_regex} found in {}", args.in_dir);
+        return 0;
+    }
+    // Ctrl+C lets running files finish, cancels queued ones and ends watching
+    if args.watch {
+        let cancellation_token = pipeline.cancellation_token();
+        if let Err(err) = ctrlc::set_handler(move || cancellation_token.cancel()) {
+            eprintln!("Failed to handle Ctrl+C! {err}");
+        }
+    }
+    // Created before the first batch runs, files added meanwhile are shrinked once it's done
+    let watcher = match args.watch {
+        true => match Watcher::after_jobs(
+            Path::new(&args.in_dir),
+            &file_regex,
+            recursive,
+            pipeline.options().clone(),
+            pipeline.jobs(),
+        ) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                eprintln!("Failed to watch {}! {err}", args.in_dir);
+                return 1;
+            }
+        },
+        false => None,
+    };
+    let thread_pool = match args.jobs {
+        Some(jobs) => ThreadPool::new(jobs.max(1)),
+        None => ThreadPool::new(num_cpus::get().max(1)),
+    };
+
+    let report = args.report.as_ref().map(|path| {
+        let path = PathBuf::from(path);
+        let format = args
+            .report_format
+            .or_else(|| ReportFormat::from_path(&path))
+            .unwrap_or_default();
+        (path, format)
+    });
+    let mut files = Vec::new();
+    let failed = match pipeline.jobs().is_empty() {
+        true => 0,
+        false => run_pipeline(&pipeline, &thread_pool, &mut files),
+    };
+    let report_written = match &report {
+        Some((path, format)) => write_report(path, *format, &files),
+        None => true,
+    };
+    let (failed, report_written) = match watcher {
+        Some(watcher) => {
+            let (watch_failed, report_written) = watch(
+                &args.in_dir,
+                watcher,
+                &thread_pool,
+                pipeline.cancellation_token(),
+                report,
+                files,
+            );
+            (failed + watch_failed, report_written)
+        }
+        None => (failed, report_written),
+    };
+
+    match (failed, report_written) {
+        (0, true) => 0,
+        _ => 1,
+    }
+}
+
+/// Write a report of `files`, returns whether that succeeded
+fn write_report(path: &Path, format: ReportFormat, files: &[FileReport]) -> bool {
+    match Report::new(files.to_vec()).write(path, format) {
+        Ok(()) => {
+            println!("Report written to {}", path.display());
+            true
+        }
+        Err(err) => {
+            eprintln!("Failed to write report! {err}");
+            false
+        }
+    }
+}
+
+/// Shrink the files `watcher` finds in `in_dir` as they appear, until `cancellation_token` is
+/// cancelled by Ctrl+C. The report is written again after every batch, `files` are the ones
+/// shrinked before watching started. Returns the number of failed files and whether the last
+/// report was written
+fn watch(
+    in_dir: &str,
+    mut watcher: Watcher,
+    thread_pool: &ThreadPool,
+    cancellation_token: CancellationToken,
+    report: Option<(PathBuf, ReportFormat)>,
+    mut files: Vec<FileReport>,
+) -> (usize, bool) {
+    println!("Watching {in_dir} for new files, press Ctrl+C to stop");
+    let (mut failed, mut report_written) = (0, true);
+    while !cancellation_token.is_cancelled() {
+        thread::sleep(POLL_INTERVAL);
+        let jobs = match watcher.poll() {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                eprintln!("Failed to read directory! {err}");
+                continue;
+            }
+        };
+        if !jobs.is_empty() {
+            let pipeline = match Pipeline::from_jobs(jobs, watcher.options().clone()) {
+                Ok(pipeline) => pipeline.with_cancellation_token(cancellation_token.clone()),
+                Err(err) => {
+                    eprintln!("{err}");
+                    continue;
+                }
+            };
+            failed += run_pipeline(&pipeline, thread_pool, &mut files);
+            if let Some((path, format)) = &report {
+                report_written = write_report(path, *format, &files);
+            }
+        }
+    }
+    println!("Stopped watching {in_dir}");
+    (failed, report_written)
+}
+
+/// Print every finished file and a summary line, returns the number of failed files. A report row
+/// is added to `files` for each of them
+fn run_pipeline(
+    pipeline: &Pipeline,
+    thread_pool: &ThreadPool,
+    files: &mut Vec<FileReport>,
+) -> usize {
+    let file_count = pipeline.jobs().len();
+    let (mut failed, mut up_to_date, mut exists) = (0, 0, 0);
+    let (mut total_size, mut total_new_size) = (0, 0);
+    // Redrawn in place below the printed files, unless the output is redirected
+    let show_progress = io::stderr().is_terminal();
+    let mut progress = Progress::new();
+    for event in pipeline.run(thread_pool) {
+        progress.update(&event);
+        let job_result = match event {
+            Event::Finished(job_result) => job_result,
+            _ => {
+                if show_progress {
+                    eprint!("\r\x1b[K{}", progress.summary());
+                }
+                continue;
+            }
+        };
+        if show_progress {
+            eprint!("\r\x1b[K");
+        }
+        files.push(FileReport::new(&job_result));
+        match job_result.outcome {
+            Outcome::Done(Shrinked {
+                new_size,
+                quality,
+                out_file,
+                larger_output,
+                variants,
+                similarity,
+                ..
+            }) => {
+                total_size += job_result.size;
+                total_new_size += new_size;
+                let out_file = match out_file {
+                    Some(out_file) => out_file.display().to_string(),
+                    None => String::from("skipped"),
+                };
+                println!(
+                    "{} -> {out_file} ({} Kb -> {} Kb, {}%{}{}{}{})",
+                    job_result.in_file.display(),
+                    job_result.size / 1024,
+                    new_size / 1024,
+                    round_percent(new_size, job_result.size),
+                    match quality {
+                        Some(quality) => format!(", quality {quality}"),
+                        None => String::new(),
+                    },
+                    match similarity {
+                        Some(similarity) => format!(
+                            ", SSIM {:.3}, PSNR {:.1} dB",
+                            similarity.ssim, similarity.psnr
+                        ),
+                        None => String::new(),
+                    },
+                    match variants.len() {
+                        0 | 1 => String::new(),
+                        count => format!(", {count} sizes"),
+                    },
+                    match larger_output {
+                        Some(LargerOutputPolicy::CopyOriginal) => ", original was smaller",
+                        Some(LargerOutputPolicy::Skip) => ", skipped because it was larger",
+                        Some(LargerOutputPolicy::Retry) => ", retried because it was larger",
+                        None => "",
+                    }
+                );
+            }
+            Outcome::UpToDate(Shrinked { new_size, .. }) => {
+                up_to_date += 1;
+                total_size += job_result.size;
+                total_new_size += new_size;
+            }
+            Outcome::Failed(err) => {
+                failed += 1;
+                eprintln!("{}: {err}", job_result.in_file.display());
+            }
+            Outcome::Cancelled => failed += 1,
+            Outcome::Exists => {
+                exists += 1;
+                println!(
+                    "{} -> {} already exists, skipped",
+                    job_result.in_file.display(),
+                    job_result.out_file.display()
+                );
+            }
+        }
+        if show_progress {
+            eprint!("{}", progress.summary());
+        }
+    }
+    if show_progress {
+        eprint!("\r\x1b[K");
+    }
+
+    println!(
+        "Shrinked {} of {file_count} files, {up_to_date} up to date, {exists} already existed: \
+         {} Kb -> {} Kb ({}%) in {} ({:.1} files/s)",
+        file_count - failed - up_to_date - exists,
+        total_size / 1024,
+        total_new_size / 1024,
+        round_percent(total_new_size, total_size),
+        format_duration(progress.elapsed()),
+        progress.files_per_second()
+    );
+    failed
+}
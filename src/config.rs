@@ -0,0 +1,80 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, RshrinkError},
+    preset::{Preset, Presets},
+};
+
+/// Searched in this order in every directory
+pub const CONFIG_FILE_NAMES: [&str; 2] = ["rshrink.toml", ".rshrink.json"];
+
+/// Compression policy of a project, discovered in the input directory or one of its parents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+// Every field is optional in the file
+#[serde(default)]
+pub struct Config {
+    /// Regular expression the file names have to match, the built in one if `None`
+    pub file_regex: Option<String>,
+    /// Include subdirectories and mirror their structure in the output folder
+    pub recursive: bool,
+    /// Settings used unless a preset is selected
+    pub defaults: Preset,
+    pub presets: Presets,
+}
+
+impl Config {
+    /// Find the closest config file in `dir` or its parents, also returns the file's path
+    pub fn discover(dir: &Path) -> Result<Option<(PathBuf, Config)>> {
+        // Relative file names have an empty parent
+        let dir = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        let dir = fs::canonicalize(dir).map_err(|err| RshrinkError::io(dir, err))?;
+        for ancestor in dir.ancestors() {
+            for file_name in CONFIG_FILE_NAMES {
+                let path = ancestor.join(file_name);
+                if path.is_file() {
+                    let config = Config::load(&path)?;
+                    return Ok(Some((path, config)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Parse a toml or json config, relative output paths are resolved against its directory
+    pub fn load(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path).map_err(|err| RshrinkError::io(path, err))?;
+        let mut config: Config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content)?,
+            _ => serde_json::from_str(&content)?,
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for preset in std::iter::once(&mut config.defaults).chain(config.presets.values_mut()) {
+            if let Some(parent_dir) = &preset.output_folder_parent_dir_path {
+                preset.output_folder_parent_dir_path =
+                    Some(dir.join(parent_dir).display().to_string());
+                preset.output_folder_parent_dir_path_enabled = true;
+            }
+            if let Some(backup_dir) = &preset.backup_dir {
+                preset.backup_dir = Some(dir.join(backup_dir).display().to_string());
+            }
+        }
+        Ok(config)
+    }
+
+    /// The preset called `name`, or the defaults
+    pub fn preset(&self, name: Option<&str>) -> Option<&Preset> {
+        match name {
+            Some(name) => self.presets.get(name),
+            None => Some(&self.defaults),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use magick_rust::MagickError;
+
+/// Every error rshrink can run into, carried through the library and shown per file in the UI
+#[derive(Debug)]
+pub enum RshrinkError {
+    /// Reading or writing a file or directory failed
+    Io { path: PathBuf, source: io::Error },
+    /// The file selection is not a valid regular expression
+    Regex(regex::Error),
+    /// Imagemagick failed to read, process or write an image
+    Magick(MagickError),
+    /// Imagemagick decoded an image but couldn't hand out its pixels
+    ExportPixels,
+    /// Even the lowest quality (and smallest allowed dimensions) exceed the target file size
+    TargetSizeUnreachable { target: u64, smallest: u64 },
+    /// ffmpeg failed to read, transcode or write a video
+    Ffmpeg(ffmpeg_next::Error),
+    /// Dimensions couldn't be parsed or are out of range
+    InvalidDimensions(String),
+    /// Path can't be handed to imagemagick or ffmpeg because it's not valid unicode
+    InvalidPath(PathBuf),
+    /// Presets couldn't be serialized or an imported file is not valid
+    Json(serde_json::Error),
+    /// A project config file is not valid
+    Toml(toml::de::Error),
+    /// An output file name template uses unknown placeholders or leaves the output folder
+    InvalidTemplate(String),
+    /// Options that can't be combined, e.g. replacing originals with several sizes
+    InvalidOptions(String),
+}
+
+pub type Result<T> = std::result::Result<T, RshrinkError>;
+
+impl RshrinkError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        RshrinkError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for RshrinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RshrinkError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            RshrinkError::Regex(err) => write!(f, "Failed to parse regular expression! {err}"),
+            RshrinkError::Magick(err) => write!(f, "Imagemagick failed! {err}"),
+            RshrinkError::ExportPixels => write!(f, "Failed to export image pixels!"),
+            RshrinkError::TargetSizeUnreachable { target, smallest } => write!(
+                f,
+                "Can't shrink below {} Kb, smallest result was {} Kb!",
+                target / 1024,
+                smallest / 1024
+            ),
+            RshrinkError::Ffmpeg(err) => write!(f, "ffmpeg failed! {err}"),
+            RshrinkError::InvalidDimensions(reason) => write!(f, "Invalid dimensions! {reason}"),
+            RshrinkError::InvalidPath(path) => {
+                write!(f, "Path is not valid unicode! {}", path.display())
+            }
+            RshrinkError::Json(err) => write!(f, "Failed to read JSON! {err}"),
+            RshrinkError::Toml(err) => write!(f, "Failed to read TOML! {err}"),
+            RshrinkError::InvalidTemplate(reason) => {
+                write!(f, "Invalid file name template! {reason}")
+            }
+            RshrinkError::InvalidOptions(reason) => write!(f, "Invalid options! {reason}"),
+        }
+    }
+}
+
+impl Error for RshrinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RshrinkError::Io { source, .. } => Some(source),
+            RshrinkError::Regex(err) => Some(err),
+            RshrinkError::Ffmpeg(err) => Some(err),
+            RshrinkError::Json(err) => Some(err),
+            RshrinkError::Toml(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<regex::Error> for RshrinkError {
+    fn from(err: regex::Error) -> Self {
+        RshrinkError::Regex(err)
+    }
+}
+
+impl From<MagickError> for RshrinkError {
+    fn from(err: MagickError) -> Self {
+        RshrinkError::Magick(err)
+    }
+}
+
+impl From<ffmpeg_next::Error> for RshrinkError {
+    fn from(err: ffmpeg_next::Error) -> Self {
+        RshrinkError::Ffmpeg(err)
+    }
+}
+
+impl From<serde_json::Error> for RshrinkError {
+    fn from(err: serde_json::Error) -> Self {
+        RshrinkError::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for RshrinkError {
+    fn from(err: toml::de::Error) -> Self {
+        RshrinkError::Toml(err)
+    }
+}
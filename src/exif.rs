@@ -0,0 +1,265 @@
+//! Just enough EXIF to keep selected tags, imagemagick can only keep or strip whole profiles
+
+/// Imagemagick stores the EXIF profile of jpegs with the APP1 identifier in front
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const TAG_ARTIST: u16 = 0x013b;
+const TAG_COPYRIGHT: u16 = 0x8298;
+const TAG_GPS_INFO: u16 = 0x8825;
+const TYPE_ASCII: u16 = 2;
+/// Tag, type, count and value or offset
+const ENTRY_SIZE: usize = 12;
+
+/// EXIF profile containing nothing but `artist` and `copyright`, `None` if both are empty
+pub fn copyright_profile(artist: Option<&str>, copyright: Option<&str>) -> Option<Vec<u8>> {
+    let entries = [(TAG_ARTIST, artist), (TAG_COPYRIGHT, copyright)]
+        .into_iter()
+        .filter_map(|(tag, value)| match value.map(str::trim) {
+            Some(value) if !value.is_empty() => {
+                let mut value = value.as_bytes().to_vec();
+                value.push(0);
+                Some((tag, value))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return None;
+    }
+
+    // Little endian header, IFD0 directly follows at offset 8
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend((entries.len() as u16).to_le_bytes());
+    // Values longer than 4 bytes are stored behind the IFD and its next IFD offset
+    let data_start = tiff.len() + entries.len() * ENTRY_SIZE + 4;
+    let mut data = Vec::new();
+    for (tag, value) in &entries {
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(TYPE_ASCII.to_le_bytes());
+        tiff.extend((value.len() as u32).to_le_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            tiff.extend(inline);
+        } else {
+            tiff.extend(((data_start + data.len()) as u32).to_le_bytes());
+            data.extend(value);
+            // Offsets have to be word aligned
+            if value.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend(data);
+
+    let mut profile = EXIF_HEADER.to_vec();
+    profile.extend(tiff);
+    Some(profile)
+}
+
+/// Empty the GPS IFD of an EXIF profile in place, all other tags are left untouched.
+/// Malformed profiles are left as they are
+pub fn remove_gps(profile: &mut [u8]) {
+    let start = match profile.starts_with(EXIF_HEADER) {
+        true => EXIF_HEADER.len(),
+        false => 0,
+    };
+    let mut tiff = match Tiff::new(&mut profile[start..]) {
+        Some(tiff) => tiff,
+        None => return,
+    };
+    // Only fails for malformed profiles, which are kept as they are
+    let _ = tiff.clear_gps();
+}
+
+struct Tiff<'a> {
+    data: &'a mut [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a mut [u8]) -> Option<Tiff<'a>> {
+        let big_endian = match data.get(0..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        Some(Tiff { data, big_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn zero(&mut self, at: usize, len: usize) -> Option<()> {
+        self.data.get_mut(at..at + len)?.fill(0);
+        Some(())
+    }
+
+    fn clear_gps(&mut self) -> Option<()> {
+        let ifd0 = self.u32(4)? as usize;
+        let gps_ifd = (0..self.u16(ifd0)? as usize)
+            .map(|i| ifd0 + 2 + i * ENTRY_SIZE)
+            .find(|&entry| self.u16(entry) == Some(TAG_GPS_INFO))
+            .and_then(|entry| self.u32(entry + 8))
+            .filter(|&offset| offset != 0)? as usize;
+
+        for i in 0..self.u16(gps_ifd)? as usize {
+            let entry = gps_ifd + 2 + i * ENTRY_SIZE;
+            let size = type_size(self.u16(entry + 2)?) * self.u32(entry + 4)? as usize;
+            if size > 4 {
+                let offset = self.u32(entry + 8)? as usize;
+                self.zero(offset, size)?;
+            }
+            self.zero(entry, ENTRY_SIZE)?;
+        }
+        // An empty IFD, the zeroed first entry doubles as next IFD offset
+        self.zero(gps_ifd, 2)
+    }
+}
+
+/// Size of a single value of an EXIF type in bytes
+fn type_size(value_type: u16) -> usize {
+    match value_type {
+        // Byte, ascii, signed byte, undefined
+        1 | 2 | 6 | 7 => 1,
+        // Short, signed short
+        3 | 8 => 2,
+        // Long, signed long, float
+        4 | 9 | 11 => 4,
+        // Rational, signed rational, double
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG_EXIF_IFD: u16 = 0x8769;
+    const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+    const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+    const TAG_GPS_LATITUDE: u16 = 0x0002;
+    const TYPE_LONG: u16 = 4;
+    const TYPE_RATIONAL: u16 = 5;
+
+    fn ifd(entries: &[(u16, u16, u32, [u8; 4])]) -> Vec<u8> {
+        let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+        for (tag, value_type, count, value) in entries {
+            ifd.extend(tag.to_le_bytes());
+            ifd.extend(value_type.to_le_bytes());
+            ifd.extend(count.to_le_bytes());
+            ifd.extend(value);
+        }
+        ifd.extend(0u32.to_le_bytes());
+        ifd
+    }
+
+    /// IFD0 with the artist and pointers to an EXIF and a GPS IFD, each with a value stored
+    /// behind it
+    fn profile() -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(ifd(&[
+            (TAG_ARTIST, TYPE_ASCII, 8, 50u32.to_le_bytes()),
+            (TAG_EXIF_IFD, TYPE_LONG, 1, 58u32.to_le_bytes()),
+            (TAG_GPS_INFO, TYPE_LONG, 1, 96u32.to_le_bytes()),
+        ]));
+        tiff.extend(b"rshrink\0");
+        tiff.extend(ifd(&[(
+            TAG_DATE_TIME_ORIGINAL,
+            TYPE_ASCII,
+            20,
+            76u32.to_le_bytes(),
+        )]));
+        tiff.extend(b"2022:05:01 12:00:00\0");
+        tiff.extend(ifd(&[
+            (TAG_GPS_LATITUDE_REF, TYPE_ASCII, 2, *b"N\0\0\0"),
+            (TAG_GPS_LATITUDE, TYPE_RATIONAL, 3, 126u32.to_le_bytes()),
+        ]));
+        for value in [48u32, 1, 8, 1, 30, 1] {
+            tiff.extend(value.to_le_bytes());
+        }
+        assert_eq!(tiff.len(), 150);
+
+        let mut profile = EXIF_HEADER.to_vec();
+        profile.extend(tiff);
+        profile
+    }
+
+    #[test]
+    fn remove_gps_empties_only_the_gps_ifd() {
+        let original = profile();
+        let mut profile = original.clone();
+        remove_gps(&mut profile);
+
+        let start = EXIF_HEADER.len();
+        // IFD0, the EXIF IFD and their values are untouched
+        assert_eq!(profile[..start + 96], original[..start + 96]);
+        let tiff = Tiff::new(&mut profile[start..]).unwrap();
+        assert_eq!(tiff.u16(8), Some(3));
+        assert_eq!(tiff.u32(8 + 2 + ENTRY_SIZE + 8), Some(58));
+        assert_eq!(tiff.u32(58 + 2 + 8), Some(76));
+        assert_eq!(&tiff.data[76..96], b"2022:05:01 12:00:00\0");
+        // The GPS IFD is empty and ends the chain, its values are gone
+        assert_eq!(tiff.u16(96), Some(0));
+        assert_eq!(tiff.u32(98), Some(0));
+        assert!(tiff.data[98..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn remove_gps_keeps_malformed_profiles() {
+        let mut unknown_byte_order = b"Exif\0\0XX*\0".to_vec();
+        remove_gps(&mut unknown_byte_order);
+        assert_eq!(unknown_byte_order, b"Exif\0\0XX*\0");
+
+        // The GPS IFD is behind the end of the profile
+        let mut truncated = profile();
+        truncated.truncate(EXIF_HEADER.len() + 96);
+        let expected = truncated.clone();
+        remove_gps(&mut truncated);
+        assert_eq!(truncated, expected);
+    }
+
+    #[test]
+    fn copyright_profile_stores_both_values() {
+        let mut profile = copyright_profile(Some("Jane Doe"), Some(" CC BY ")).unwrap();
+        assert!(profile.starts_with(EXIF_HEADER));
+        let tiff = Tiff::new(&mut profile[EXIF_HEADER.len()..]).unwrap();
+        assert_eq!(tiff.u32(4), Some(8));
+        assert_eq!(tiff.u16(8), Some(2));
+
+        // Artist is longer than 4 bytes and stored behind the IFD
+        let artist = 8 + 2;
+        assert_eq!(tiff.u16(artist), Some(TAG_ARTIST));
+        assert_eq!(tiff.u32(artist + 4), Some(9));
+        let offset = tiff.u32(artist + 8).unwrap() as usize;
+        assert_eq!(offset % 2, 0);
+        assert_eq!(&tiff.data[offset..offset + 9], b"Jane Doe\0");
+        // The copyright is trimmed
+        let copyright = artist + ENTRY_SIZE;
+        assert_eq!(tiff.u16(copyright), Some(TAG_COPYRIGHT));
+        assert_eq!(tiff.u32(copyright + 4), Some(6));
+        let offset = tiff.u32(copyright + 8).unwrap() as usize;
+        assert_eq!(&tiff.data[offset..offset + 6], b"CC BY\0");
+    }
+
+    #[test]
+    fn copyright_profile_is_none_without_values() {
+        assert!(copyright_profile(None, Some("  ")).is_none());
+    }
+}
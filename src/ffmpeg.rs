@@ -0,0 +1,258 @@
+use std::sync::Once;
+
+use ffmpeg_next::{
+    codec, decoder, encoder,
+    format::{self, Pixel},
+    frame, media, picture,
+    software::scaling,
+    Dictionary, Error, Packet, Rational,
+};
+
+use crate::{
+    error::Result,
+    utils::{Dimensions, ResizeMode},
+};
+
+static START: Once = Once::new();
+
+/// Initialize ffmpeg, has to be called before any file is opened
+pub fn init() {
+    START.call_once(|| {
+        if let Err(err) = ffmpeg_next::init() {
+            eprintln!("Failed to initialize ffmpeg! {err}");
+        }
+    });
+}
+
+/// Video codec used for the output container
+enum VideoCodec {
+    H264,
+    Vp9,
+}
+
+impl VideoCodec {
+    fn for_file(out_file: &str) -> VideoCodec {
+        match out_file.to_lowercase().ends_with(".webm") {
+            true => VideoCodec::Vp9,
+            false => VideoCodec::H264,
+        }
+    }
+
+    fn encoder_name(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::Vp9 => "libvpx-vp9",
+        }
+    }
+
+    /// Map the 1..=100 compression quality onto the encoder's constant rate factor, lower is
+    /// better. A quality of 85 results in roughly the encoder's default
+    fn crf(&self, compression_quality: usize) -> usize {
+        let compression_quality = compression_quality.clamp(1, 100);
+        match self {
+            VideoCodec::H264 => 18 + (100 - compression_quality) * 33 / 99,
+            VideoCodec::Vp9 => 24 + (100 - compression_quality) * 39 / 99,
+        }
+    }
+}
+
+/// Re-encode the video stream of `in_file`, audio streams are copied and metadata is dropped.
+/// `progress` is called with the fraction of the video that has been processed so far
+pub fn perform_ffmpeg<F>(
+    in_file: &str,
+    out_file: &str,
+    dims: Option<&Dimensions>,
+    compression_quality: usize,
+    mut progress: F,
+) -> Result<()>
+where
+    F: FnMut(f32),
+{
+    let mut ictx = format::input(&in_file)?;
+    let mut octx = format::output(&out_file)?;
+    let video_codec = VideoCodec::for_file(out_file);
+    let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+    let (video_index, video_time_base, video_parameters) = {
+        let input = ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        (input.index(), input.time_base(), input.parameters())
+    };
+    let mut decoder = codec::context::Context::from_parameters(video_parameters)?
+        .decoder()
+        .video()?;
+    let (width, height) = output_size(decoder.width(), decoder.height(), dims);
+
+    // Map input stream indexes to output stream indexes, only video and audio are kept
+    let mut stream_mapping: Vec<Option<usize>> = vec![None; ictx.nb_streams() as usize];
+    let mut input_time_bases = vec![Rational(0, 1); ictx.nb_streams() as usize];
+    let mut video_ost_index = 0;
+    let mut video_encoder = None;
+    for input in ictx.streams() {
+        input_time_bases[input.index()] = input.time_base();
+        if input.index() == video_index {
+            let encoder_codec =
+                encoder::find_by_name(video_codec.encoder_name()).ok_or(Error::EncoderNotFound)?;
+            let mut ost = octx.add_stream(encoder_codec)?;
+            let mut encoder = codec::context::Context::from_parameters(ost.parameters())?
+                .encoder()
+                .video()?;
+            encoder.set_width(width);
+            encoder.set_height(height);
+            encoder.set_aspect_ratio(decoder.aspect_ratio());
+            encoder.set_format(Pixel::YUV420P);
+            encoder.set_frame_rate(decoder.frame_rate());
+            encoder.set_time_base(video_time_base);
+            if global_header {
+                encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+            }
+            let mut options = Dictionary::new();
+            options.set("crf", &video_codec.crf(compression_quality).to_string());
+            if let VideoCodec::Vp9 = video_codec {
+                // Constant quality mode
+                encoder.set_bit_rate(0);
+            }
+            let encoder = encoder.open_as_with(encoder_codec, options)?;
+            ost.set_parameters(&encoder);
+            video_ost_index = ost.index();
+            stream_mapping[input.index()] = Some(ost.index());
+            video_encoder = Some(encoder);
+        } else if input.parameters().medium() == media::Type::Audio {
+            let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+            ost.set_parameters(input.parameters());
+            // Let the muxer pick a codec tag suitable for the container
+            unsafe {
+                (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            }
+            stream_mapping[input.index()] = Some(ost.index());
+        }
+    }
+    let mut encoder = video_encoder.ok_or(Error::StreamNotFound)?;
+
+    // Metadata of the input is not copied
+    octx.write_header()?;
+
+    let mut scaler = scaling::Context::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::YUV420P,
+        width,
+        height,
+        scaling::Flags::BILINEAR,
+    )?;
+    let video_ost_time_base = octx
+        .stream(video_ost_index)
+        .ok_or(Error::StreamNotFound)?
+        .time_base();
+    let duration = ictx.duration() as f64 / f64::from(ffmpeg_next::ffi::AV_TIME_BASE);
+
+    for (stream, mut packet) in ictx.packets() {
+        let ost_index = match stream_mapping[stream.index()] {
+            Some(ost_index) => ost_index,
+            None => continue,
+        };
+        if stream.index() == video_index {
+            if duration > 0. {
+                let position = packet.pts().unwrap_or(0) as f64 * f64::from(video_time_base);
+                progress((position / duration).clamp(0., 1.) as f32);
+            }
+            decoder.send_packet(&packet)?;
+            encode_decoded_frames(
+                &mut decoder,
+                &mut scaler,
+                &mut encoder,
+                &mut octx,
+                (video_ost_index, video_time_base, video_ost_time_base),
+            )?;
+        } else {
+            // Copy audio packets as they are
+            let ost_time_base = octx
+                .stream(ost_index)
+                .ok_or(Error::StreamNotFound)?
+                .time_base();
+            packet.rescale_ts(input_time_bases[stream.index()], ost_time_base);
+            packet.set_position(-1);
+            packet.set_stream(ost_index);
+            packet.write_interleaved(&mut octx)?;
+        }
+    }
+
+    // Flush frames still buffered in the decoder and encoder
+    decoder.send_eof()?;
+    encode_decoded_frames(
+        &mut decoder,
+        &mut scaler,
+        &mut encoder,
+        &mut octx,
+        (video_ost_index, video_time_base, video_ost_time_base),
+    )?;
+    encoder.send_eof()?;
+    write_encoded_packets(
+        &mut encoder,
+        &mut octx,
+        (video_ost_index, video_time_base, video_ost_time_base),
+    )?;
+    octx.write_trailer()?;
+    progress(1.);
+
+    Ok(())
+}
+
+/// Output stream index, encoder time base and output stream time base
+type StreamTimeBases = (usize, Rational, Rational);
+
+fn encode_decoded_frames(
+    decoder: &mut decoder::Video,
+    scaler: &mut scaling::Context,
+    encoder: &mut encoder::video::Encoder,
+    octx: &mut format::context::Output,
+    stream: StreamTimeBases,
+) -> Result<()> {
+    let mut decoded = frame::Video::empty();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        let mut scaled = frame::Video::empty();
+        scaler.run(&decoded, &mut scaled)?;
+        scaled.set_pts(decoded.timestamp());
+        scaled.set_kind(picture::Type::None);
+        encoder.send_frame(&scaled)?;
+        write_encoded_packets(encoder, octx, stream)?;
+    }
+    Ok(())
+}
+
+fn write_encoded_packets(
+    encoder: &mut encoder::video::Encoder,
+    octx: &mut format::context::Output,
+    (ost_index, encoder_time_base, ost_time_base): StreamTimeBases,
+) -> Result<()> {
+    let mut encoded = Packet::empty();
+    while encoder.receive_packet(&mut encoded).is_ok() {
+        encoded.set_stream(ost_index);
+        encoded.rescale_ts(encoder_time_base, ost_time_base);
+        encoded.write_interleaved(octx)?;
+    }
+    Ok(())
+}
+
+/// Scale the video according to `dims`, yuv420p requires even dimensions
+fn output_size(width: u32, height: u32, dims: Option<&Dimensions>) -> (u32, u32) {
+    let (width, height) = match dims {
+        // Videos aren't cropped, fill fits them instead
+        Some(dims) if dims.mode == ResizeMode::Fill => {
+            let dims = Dimensions {
+                mode: ResizeMode::Fit,
+                ..dims.clone()
+            };
+            dims.scaled_size(width as usize, height as usize)
+        }
+        Some(dims) => dims.scaled_size(width as usize, height as usize),
+        None => (width as usize, height as usize),
+    };
+    (
+        (width as u32 / 2 * 2).max(2),
+        (height as u32 / 2 * 2).max(2),
+    )
+}
@@ -1,43 +1,195 @@
-use std::{ffi::OsString, fs, io};
+use std::{
+    ffi::OsString,
+    fs::{self, File, FileTimes, Metadata},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use regex::Regex;
 
-pub fn create_dir_if_not_exists(dir: &str) -> io::Result<()> {
-    fs::create_dir(dir)?;
-    Ok(())
+use crate::error::{Result, RshrinkError};
+
+pub const DEFAULT_OUT_DIR: &str = "_rshrinked";
+pub const DEFAULT_REGEX: &str =
+    r".*.(jpg|png|jpeg|JPG|PNG|JPEG|mp4|mov|webm|mkv|MP4|MOV|WEBM|MKV)$";
+/// Extensions of files that are shrinked with ffmpeg instead of imagemagick
+pub const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "mov", "webm", "mkv"];
+
+pub fn is_video(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Size and modification time of `file`, `None` if it can't be read
+pub fn fingerprint(file: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(file).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Whether both paths name the same folder, also if they are spelled differently
+pub fn is_same_dir(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
-fn parse_file(file_sel: &str, file_name: &str) -> bool {
-    match Regex::new(file_sel) {
-        Ok(reg) => reg.is_match(file_name),
-        Err(err) => {
-            eprintln!("Failed to parse regular expression! {err}");
-            false
+/// Hidden file next to `file` that is written first and renamed over it once complete. Keeps the
+/// extension, the encoders pick the format by it. `id` tells apart jobs of a batch whose files
+/// share a stem
+pub fn temp_file(file: &Path, id: usize) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(file.file_stem().unwrap_or_default());
+    name.push(format!(".rshrink-tmp-{id}"));
+    if let Some(extension) = file.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    file.with_file_name(name)
+}
+
+/// Atomically replace `original` with `new_file` written in the same directory, the result ends
+/// up at `target` with the original's permissions and timestamps. The original is kept at
+/// `backup` first, nothing is replaced if a file already exists there
+pub fn replace_file(
+    original: &Path,
+    new_file: &Path,
+    target: &Path,
+    backup: Option<&Path>,
+) -> Result<()> {
+    let metadata = fs::metadata(original).map_err(|err| RshrinkError::io(original, err))?;
+    let file = File::options()
+        .write(true)
+        .open(new_file)
+        .map_err(|err| RshrinkError::io(new_file, err))?;
+    preserve_metadata(&file, &metadata)
+        .and_then(|_| file.sync_all())
+        .map_err(|err| RshrinkError::io(new_file, err))?;
+    drop(file);
+
+    if let Some(backup) = backup {
+        // It belongs to another original or an earlier run, an original is never replaced
+        // without a backup of its own
+        if backup.exists() {
+            return Err(RshrinkError::io(
+                backup,
+                io::Error::from(io::ErrorKind::AlreadyExists),
+            ));
         }
+        if let Some(backup_dir) = backup.parent() {
+            create_dir_if_not_exists(&backup_dir.to_string_lossy())?;
+        }
+        keep_backup(original, backup, &metadata).map_err(|err| RshrinkError::io(backup, err))?;
+    }
+    fs::rename(new_file, target).map_err(|err| RshrinkError::io(target, err))?;
+    // Converted files get another extension, the original is still there
+    if target != original {
+        fs::remove_file(original).map_err(|err| RshrinkError::io(original, err))?;
+    }
+    // Make the rename itself durable, directories can't be opened on every platform
+    if let Some(dir) = target.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn preserve_metadata(file: &File, metadata: &Metadata) -> io::Result<()> {
+    file.set_permissions(metadata.permissions())?;
+    file.set_times(
+        FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?),
+    )
+}
+
+/// A hard link keeps the original as it is without copying, copy it across file systems
+fn keep_backup(original: &Path, backup: &Path, metadata: &Metadata) -> io::Result<()> {
+    if fs::hard_link(original, backup).is_ok() {
+        return Ok(());
     }
+    fs::copy(original, backup)?;
+    let file = File::options().write(true).open(backup)?;
+    preserve_metadata(&file, metadata)?;
+    file.sync_all()
+}
+
+/// Create `dir` and all of its missing parents
+pub fn create_dir_if_not_exists(dir: &str) -> Result<()> {
+    if !Path::new(dir).is_dir() {
+        fs::create_dir_all(dir).map_err(|err| RshrinkError::io(dir, err))?;
+    }
+    Ok(())
 }
 
-pub fn filter_files(files: Vec<OsString>, file_sel: &str) -> Vec<OsString> {
-    files
-        .iter()
-        .cloned()
+pub fn parse_file(file_sel: &str, file_name: &str) -> Result<bool> {
+    Ok(Regex::new(file_sel)?.is_match(file_name))
+}
+
+pub fn filter_files(files: Vec<OsString>, file_sel: &str) -> Result<Vec<OsString>> {
+    let reg = Regex::new(file_sel)?;
+    Ok(files
+        .into_iter()
         .filter(|f| match f.to_str() {
-            Some(file_name) => parse_file(file_sel, file_name),
+            Some(file_name) => reg.is_match(file_name),
             None => false,
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>())
 }
 
-pub fn list_files(path: &str) -> io::Result<Vec<OsString>> {
-    let entries = fs::read_dir(path)?
-        .filter_map(|res| match res {
-            Ok(e) => Some(e.file_name()),
-            Err(err) => {
-                eprintln!("Failed to read file! {err}");
-                None
-            }
+pub fn list_files(path: &str) -> Result<Vec<OsString>> {
+    fs::read_dir(path)
+        .map_err(|err| RshrinkError::io(path, err))?
+        .map(|res| match res {
+            Ok(e) => Ok(e.file_name()),
+            Err(err) => Err(RshrinkError::io(path, err)),
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>>>()
+}
 
-    Ok(entries)
+/// Walk `dir` recursively and collect all files whose name matches `file_sel`. `exclude` and
+/// everything below it is skipped, symlinked directories are not followed
+pub fn list_files_recursive(
+    dir: &Path,
+    file_sel: &str,
+    exclude: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let reg = Regex::new(file_sel)?;
+    let exclude = exclude.and_then(|exclude| fs::canonicalize(exclude).ok());
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        if let (Some(exclude), Ok(canonical)) = (&exclude, fs::canonicalize(&dir)) {
+            if &canonical == exclude {
+                continue;
+            }
+        }
+        let mut entries = fs::read_dir(&dir)
+            .map_err(|err| RshrinkError::io(&dir, err))?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| RshrinkError::io(&dir, err))?;
+        // Keep the order stable between runs
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let file_type = entry
+                .file_type()
+                .map_err(|err| RshrinkError::io(entry.path(), err))?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| reg.is_match(name))
+            {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
 }
@@ -1,84 +1,194 @@
 use eframe::{
     egui::{
-        self, menu, Button, CentralPanel, Context, Grid, Id, Label, LayerId, Layout, Order,
-        RichText, ScrollArea, Slider, Spinner, TextEdit, TextStyle, TopBottomPanel, Ui, Visuals,
-        Widget, Window,
+        self, menu, Button, CentralPanel, Checkbox, CollapsingHeader, ColorImage, ComboBox,
+        Context, DragValue, Grid, Id, Label, LayerId, Layout, Order, ProgressBar, RichText,
+        ScrollArea, Sense, Slider, Spinner, TextEdit, TextStyle, TextureHandle, TopBottomPanel, Ui,
+        Visuals, Widget, Window,
     },
-    emath::{Align2, Vec2},
-    epaint::Color32,
+    emath::{pos2, Align2, Rect, Vec2},
+    epaint::{Color32, Stroke},
     App, CreationContext, Frame,
 };
-use magick_rust::magick_wand_genesis;
-use regex::Regex;
 use std::{
-    fs::File,
-    sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc, Once,
-    },
+    cmp::Ordering,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    filesystem::create_dir_if_not_exists,
-    imagemagick::perform_magick,
+    config::Config,
+    error::{Result, RshrinkError},
+    filesystem::{is_video, list_files_recursive, parse_file, DEFAULT_REGEX},
+    imagemagick::{
+        self, decode_pixels, render_magick, JpegColorspace, MagickOptions, OutputFormat, Pixels,
+        PngMode, Similarity, Subsampling,
+    },
+    pipeline::{
+        Backup, CancellationToken, Collision, Event, Job, LargerOutputPolicy, Options, Outcome,
+        Pipeline, Shrinked, Srcset, Variant, DEFAULT_TEMPLATE,
+    },
+    preset::{export_presets, import_presets, Preset, Presets},
+    progress::Progress,
+    report::{FileReport, Report, ReportFormat, Status},
+    template::{self, PLACEHOLDERS},
     threadpool::ThreadPool,
-    utils::{round_percent, Dimensions},
+    utils::{round_percent, Dimensions, ResizeMode},
+    watch::Watcher,
 };
 
-static START: Once = Once::new();
-const DEFAULT_OUT_DIR: &str = "_rshrinked";
-const DEFAULT_REGEX: &str = r".*.(jpg|png|jpeg|JPG|PNG|JPEG)$";
 const PADDING: f32 = 5.0;
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 8.0;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
+// Settings stored by an older version miss new fields
+#[serde(default)]
 struct Settings {
-    dimensions: Dimensions,
-    change_dimensions: bool,
-    compression_quality: usize,
-    output_folder_name: String,
-    output_folder_parent_dir_path: Option<String>,
-    output_folder_parent_dir_path_enabled: bool,
+    /// Settings currently shown in the controls, stored flat so older settings still load
+    #[serde(flatten)]
+    preset: Preset,
+    presets: Presets,
+    /// Name of the preset `preset` was loaded from or last saved to
+    selected_preset: Option<String>,
     light_mode: bool,
-}
-
-impl Default for Settings {
-    fn default() -> Self {
-        Self {
-            dimensions: Dimensions::default(),
-            change_dimensions: true,
-            compression_quality: 85,
-            output_folder_name: String::from(DEFAULT_OUT_DIR),
-            output_folder_parent_dir_path_enabled: false,
-            output_folder_parent_dir_path: None,
-            light_mode: false,
-        }
-    }
+    /// Column the file table is sorted by, in the order the files were selected if `None`
+    sort_column: Option<Column>,
+    sort_descending: bool,
+    /// Show the files in a section per parent folder
+    group_by_folder: bool,
 }
 
 #[derive(Clone)]
 struct FileSize {
     original: u64,
-    new: Arc<AtomicU64>,
+    new: u64,
 }
 
 impl FileSize {
     fn new(original: u64) -> Self {
         Self {
             original,
-            new: Arc::new(AtomicU64::new(original)),
+            new: original,
+        }
+    }
+
+    /// Percentage of the original size that was saved, negative if the new file is larger
+    fn savings(&self) -> f32 {
+        match self.original {
+            0 => 0.,
+            _ => 100. - round_percent(self.new, self.original),
         }
     }
 }
 
-#[derive(Clone)]
+/// Columns of the file table that can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Column {
+    Folder,
+    Name,
+    Size,
+    NewSize,
+    Savings,
+    Dimensions,
+    NewDimensions,
+    Duration,
+    Status,
+}
+
+impl Column {
+    const ALL: [Column; 9] = [
+        Column::Folder,
+        Column::Name,
+        Column::Size,
+        Column::NewSize,
+        Column::Savings,
+        Column::Dimensions,
+        Column::NewDimensions,
+        Column::Duration,
+        Column::Status,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Column::Folder => "Folder",
+            Column::Name => "Name",
+            Column::Size => "Size",
+            Column::NewSize => "New size",
+            Column::Savings => "Saved",
+            Column::Dimensions => "Dimensions",
+            Column::NewDimensions => "New dimensions",
+            Column::Duration => "Duration",
+            Column::Status => "Status",
+        }
+    }
+
+    fn compare(&self, a: &SelectedFile, b: &SelectedFile) -> Ordering {
+        // Unknown dimensions come first
+        let pixels = |dimensions: Option<(usize, usize)>| dimensions.map(|(w, h)| w * h);
+        match self {
+            Column::Folder => a.parent_folder.cmp(&b.parent_folder),
+            Column::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Column::Size => a.size.original.cmp(&b.size.original),
+            Column::NewSize => a.size.new.cmp(&b.size.new),
+            Column::Savings => a.size.savings().total_cmp(&b.size.savings()),
+            Column::Dimensions => pixels(a.original_dimensions).cmp(&pixels(b.original_dimensions)),
+            Column::NewDimensions => pixels(a.dimensions).cmp(&pixels(b.dimensions)),
+            Column::Duration => a.duration.cmp(&b.duration),
+            Column::Status => (a.status as u8).cmp(&(b.status as u8)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileStatus {
+    Pending,
+    Done,
+    /// Skipped because it didn't change since the last run
+    UpToDate,
+    Failed,
+    Cancelled,
+    /// Skipped because its output file already existed
+    Exists,
+}
+
 struct SelectedFile {
     path: String,
     parent_folder: String,
     name: String,
     size: FileSize,
-    done: Arc<AtomicBool>,
+    status: FileStatus,
+    /// A worker picked the file up, it's waiting in the queue otherwise
+    started: bool,
+    /// Only reported for videos
+    progress: Option<f32>,
+    /// Why the file couldn't be shrinked, set together with `FileStatus::Failed`
+    error: Option<RshrinkError>,
+    /// Set together with `FileStatus::Done`
+    out_file: Option<PathBuf>,
+    /// Where the original is moved to if it's replaced, the preview compares with it
+    backup_file: Option<PathBuf>,
+    /// Quality the image was encoded with, differs per file in max file size mode
+    quality: Option<usize>,
+    /// Set if the first result was larger than the original
+    larger_output: Option<LargerOutputPolicy>,
+    /// Selected folder the file was found in, its subdirectories are mirrored in the output
+    root: Option<PathBuf>,
+    /// Responsive sizes that were written
+    variants: Vec<Variant>,
+    /// Compared with the original if quality metrics are enabled
+    similarity: Option<Similarity>,
+    original_dimensions: Option<(usize, usize)>,
+    dimensions: Option<(usize, usize)>,
+    /// Time the last run spent on the file
+    duration: Option<Duration>,
+    /// Checked in the table, for removing several files at once
+    selected: bool,
 }
 
 impl SelectedFile {
@@ -86,25 +196,193 @@ impl SelectedFile {
         let path_vec = path.split('/').collect::<Vec<_>>();
         let count = path_vec.len();
 
-        let file = File::open(&path).expect("Failed to open file");
-        let file_size = File::metadata(&file).unwrap().len();
+        // A file that can't be read is still listed, so the user sees why it's not shrinked
+        let (file_size, error) = match fs::metadata(&path) {
+            Ok(metadata) => (metadata.len(), None),
+            Err(err) => (0, Some(RshrinkError::io(&path, err))),
+        };
 
         SelectedFile {
             path: path.clone(),
             parent_folder: path_vec[0..count - 1].join("/"),
             name: path_vec[count - 1].to_string(),
             size: FileSize::new(file_size),
-            done: Arc::new(AtomicBool::new(false)),
+            status: match error {
+                Some(_) => FileStatus::Failed,
+                None => FileStatus::Pending,
+            },
+            started: false,
+            progress: None,
+            error,
+            out_file: None,
+            backup_file: None,
+            quality: None,
+            larger_output: None,
+            root: None,
+            variants: Vec::new(),
+            similarity: None,
+            original_dimensions: None,
+            dimensions: None,
+            duration: None,
+            selected: false,
+        }
+    }
+
+    fn in_tree(mut self, root: PathBuf) -> SelectedFile {
+        self.root = Some(root);
+        self
+    }
+
+    /// Store the result of a finished or up to date job
+    fn apply(&mut self, shrinked: Shrinked) {
+        self.size.new = shrinked.new_size;
+        self.out_file = shrinked.out_file;
+        self.quality = shrinked.quality;
+        self.larger_output = shrinked.larger_output;
+        self.variants = shrinked.variants;
+        self.similarity = shrinked.similarity;
+        self.original_dimensions = shrinked.original_dimensions;
+        self.dimensions = shrinked.dimensions;
+    }
+
+    /// File the preview shows as the original, a replaced original only survives in its backup
+    fn original_file(&self) -> Option<String> {
+        match self.out_file.as_deref() == Some(Path::new(&self.path)) {
+            true => self
+                .backup_file
+                .as_ref()
+                .map(|backup_file| backup_file.display().to_string()),
+            false => Some(self.path.clone()),
         }
     }
+
+    /// Whether the new size is known, the footer's totals are summed from these files
+    fn has_result(&self) -> bool {
+        matches!(
+            self.status,
+            FileStatus::Done | FileStatus::UpToDate | FileStatus::Exists
+        )
+    }
+
+    /// Row of an exported report
+    fn report(&self) -> FileReport {
+        FileReport {
+            in_file: PathBuf::from(&self.path),
+            out_file: self.out_file.clone(),
+            status: match self.status {
+                FileStatus::Pending => Status::Pending,
+                FileStatus::Done => Status::shrinked(self.larger_output),
+                FileStatus::UpToDate => Status::UpToDate,
+                FileStatus::Failed => Status::Failed,
+                FileStatus::Cancelled => Status::Cancelled,
+                FileStatus::Exists => Status::Exists,
+            },
+            size: self.size.original,
+            new_size: match self.has_result() {
+                true => Some(self.size.new),
+                false => None,
+            },
+            original_dimensions: self.original_dimensions,
+            dimensions: self.dimensions,
+            quality: self.quality,
+            similarity: self.similarity,
+            seconds: self.duration.map_or(0., |duration| duration.as_secs_f64()),
+            error: self.error.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+/// Original and compressed image decoded in the background
+struct PreviewImages {
+    original: Pixels,
+    original_size: u64,
+    compressed: Pixels,
+    compressed_size: u64,
+    /// Whether `compressed` is the output file or an in-memory render with the current settings
+    from_output_file: bool,
+}
+
+impl PreviewImages {
+    fn load(
+        in_file: &str,
+        out_file: Option<&Path>,
+        options: &MagickOptions,
+    ) -> Result<PreviewImages> {
+        let original = fs::read(in_file).map_err(|err| RshrinkError::io(in_file, err))?;
+        let compressed = match out_file {
+            Some(out_file) => fs::read(out_file).map_err(|err| RshrinkError::io(out_file, err))?,
+            None => render_magick(in_file, options)?,
+        };
+        Ok(PreviewImages {
+            original: decode_pixels(&original)?,
+            original_size: original.len() as u64,
+            compressed: decode_pixels(&compressed)?,
+            compressed_size: compressed.len() as u64,
+            from_output_file: out_file.is_some(),
+        })
+    }
+}
+
+struct PreviewTextures {
+    original: TextureHandle,
+    compressed: TextureHandle,
+    original_size: u64,
+    compressed_size: u64,
+    from_output_file: bool,
+}
+
+/// Side-by-side comparison of a single file, the original is shown left of the split
+struct Preview {
+    name: String,
+    loading: Option<Receiver<Result<PreviewImages>>>,
+    textures: Option<PreviewTextures>,
+    error: Option<RshrinkError>,
+    /// Fraction of the image width showing the original
+    split: f32,
+    /// Screen points per image pixel, 1.0 is 1:1
+    zoom: f32,
+    pan: Vec2,
+    open: bool,
+}
+
+/// Folder whose new files are shrinked as they appear
+struct Watching {
+    dir: PathBuf,
+    /// Settings at the time watching started
+    options: Options,
+    jobs: Receiver<Result<Vec<Job>>>,
+    /// Stops the background thread polling the folder
+    cancellation_token: CancellationToken,
+    /// Jobs waiting for the running batch to finish
+    queue: Vec<Job>,
 }
 
 #[derive(Default)]
 pub struct RshrinkApp {
     selected_files: Vec<SelectedFile>,
     total_file_size: u64,
-    total_new_file_size: Arc<AtomicU64>,
+    total_new_file_size: u64,
     thread_pool: ThreadPool,
+    events: Option<Receiver<Event>>,
+    /// Index of the first selected file of the running batch
+    events_offset: usize,
+    cancellation_token: CancellationToken,
+    dimensions_error: Option<RshrinkError>,
+    /// Name entered for a new or renamed preset
+    preset_name: String,
+    preset_error: Option<RshrinkError>,
+    /// Config file found next to the selected files and its content
+    project_config: Option<(PathBuf, Config)>,
+    config_error: Option<RshrinkError>,
+    watching: Option<Watching>,
+    /// Files shrinked while watching a folder, oldest first
+    watch_log: Vec<String>,
+    /// Overall progress of the running batch
+    progress: Progress,
+    /// Only files whose path contains this are shown
+    filter: String,
+    report_error: Option<RshrinkError>,
+    preview: Option<Preview>,
     is_running: bool,
     has_run_once: bool,
     settings_dialog_opened: bool,
@@ -113,14 +391,28 @@ pub struct RshrinkApp {
 
 impl App for RshrinkApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        let mut last_folder = String::new();
+        self.poll_events(ctx);
+        self.poll_watcher();
         // Footer (first, because of CentralPanel filling the remaininng space)
         render_footer(
             ctx,
             self.total_file_size,
-            Arc::clone(&self.total_new_file_size),
+            self.total_new_file_size,
             self.has_run_once,
             self.selected_files.len(),
+            self.selected_files
+                .iter()
+                .filter(|selected_file| {
+                    matches!(
+                        selected_file.larger_output,
+                        Some(LargerOutputPolicy::CopyOriginal | LargerOutputPolicy::Skip)
+                    )
+                })
+                .count(),
+            self.selected_files
+                .iter()
+                .filter(|selected_file| selected_file.status == FileStatus::UpToDate)
+                .count(),
         );
         CentralPanel::default().show(ctx, |ui| {
             // Render menu
@@ -134,8 +426,9 @@ impl App for RshrinkApp {
             });
             ui.add_space(5.0);
             // Files to shrink
-            self.render_main(ui, &mut last_folder);
+            self.render_main(ui);
         });
+        self.render_preview(ctx);
         self.detect_files_being_dropped(ctx);
     }
 
@@ -149,9 +442,7 @@ impl App for RshrinkApp {
 impl RshrinkApp {
     pub fn new(cc: &CreationContext<'_>) -> Self {
         // Init imagemagick
-        START.call_once(|| {
-            magick_wand_genesis();
-        });
+        imagemagick::init();
 
         // Retrieve stored settings from file
         let mut stored_settings: Option<Settings> = None;
@@ -199,42 +490,47 @@ impl RshrinkApp {
                         .spacing([60., 10.])
                         .show(ui, |ui| {
                             ui.checkbox(
-                                &mut self.settings.output_folder_parent_dir_path_enabled,
+                                &mut self.settings.preset.output_folder_parent_dir_path_enabled,
                                 "Change file directory",
                             );
-                            if self.settings.output_folder_parent_dir_path == None
-                                && self.settings.output_folder_parent_dir_path_enabled
+                            if self.settings.preset.output_folder_parent_dir_path == None
+                                && self.settings.preset.output_folder_parent_dir_path_enabled
                             {
                                 match rfd::FileDialog::new().pick_folder() {
                                     Some(folder) => match folder.to_str() {
                                         Some(f) => {
-                                            self.settings.output_folder_parent_dir_path =
+                                            self.settings.preset.output_folder_parent_dir_path =
                                                 Some(String::from(f));
                                         }
                                         None => {
-                                            self.settings.output_folder_parent_dir_path_enabled =
-                                                false
+                                            self.settings
+                                                .preset
+                                                .output_folder_parent_dir_path_enabled = false
                                         }
                                     },
                                     None => {
-                                        self.settings.output_folder_parent_dir_path_enabled = false
+                                        self.settings.preset.output_folder_parent_dir_path_enabled =
+                                            false
                                     }
                                 }
-                            } else if !self.settings.output_folder_parent_dir_path_enabled {
-                                self.settings.output_folder_parent_dir_path = None;
+                            } else if !self.settings.preset.output_folder_parent_dir_path_enabled {
+                                self.settings.preset.output_folder_parent_dir_path = None;
                             }
                             ui.add(
                                 Label::new(
                                     RichText::new(
-                                        match &self.settings.output_folder_parent_dir_path {
+                                        match &self.settings.preset.output_folder_parent_dir_path {
                                             Some(path) => {
                                                 format!(
                                                     "{}/{}",
-                                                    path, self.settings.output_folder_name
+                                                    path, self.settings.preset.output_folder_name
                                                 )
                                             }
                                             None => {
-                                                format!("./{}", self.settings.output_folder_name)
+                                                format!(
+                                                    "./{}",
+                                                    self.settings.preset.output_folder_name
+                                                )
                                             }
                                         },
                                     )
@@ -246,10 +542,75 @@ impl RshrinkApp {
                             ui.wrap_text();
                             ui.label(RichText::new("Output folder name"));
                             ui.add(
-                                TextEdit::singleline(&mut self.settings.output_folder_name)
+                                TextEdit::singleline(&mut self.settings.preset.output_folder_name)
                                     .hint_text("Same folder with \"min-\" prefix"),
                             );
                             ui.end_row();
+                            ui.label("File name");
+                            ui.add(
+                                TextEdit::singleline(&mut self.settings.preset.file_name_template)
+                                    .hint_text(DEFAULT_TEMPLATE),
+                            )
+                            .on_hover_text(format!(
+                                "Path inside the output folder, placeholders: {{{}}}",
+                                PLACEHOLDERS.join("}, {")
+                            ));
+                            ui.end_row();
+                            if !self.settings.preset.file_name_template.is_empty() {
+                                if let Err(err) =
+                                    template::validate(&self.settings.preset.file_name_template)
+                                {
+                                    ui.label("");
+                                    ui.colored_label(Color32::RED, err.to_string());
+                                    ui.end_row();
+                                }
+                            }
+                            ui.label("If file exists");
+                            ComboBox::from_id_source("collision")
+                                .selected_text(self.settings.preset.collision.name())
+                                .show_ui(ui, |ui| {
+                                    for collision in Collision::ALL {
+                                        ui.selectable_value(
+                                            &mut self.settings.preset.collision,
+                                            collision,
+                                            collision.name(),
+                                        );
+                                    }
+                                });
+                            ui.end_row();
+                            ui.checkbox(&mut self.settings.preset.replace_originals, "Replace originals")
+                                .on_hover_text("Overwrite the original files instead of writing into the output folder");
+                            ui.end_row();
+                            // Sizes and templates are set elsewhere, compressing is disabled
+                            if let Err(err @ RshrinkError::InvalidOptions(_)) =
+                                self.settings.preset.options().validate()
+                            {
+                                ui.label("");
+                                ui.colored_label(Color32::RED, err.to_string());
+                                ui.end_row();
+                            }
+                            let preset = &mut self.settings.preset;
+                            ui.add_enabled_ui(preset.replace_originals, |ui| {
+                                ComboBox::from_label("Backup")
+                                    .selected_text(preset.backup.name())
+                                    .show_ui(ui, |ui| {
+                                        for backup in Backup::ALL {
+                                            ui.selectable_value(&mut preset.backup, backup, backup.name());
+                                        }
+                                    });
+                            });
+                            if preset.replace_originals && preset.backup == Backup::Dir {
+                                let text = match &preset.backup_dir {
+                                    Some(backup_dir) => backup_dir.as_str(),
+                                    None => "Select backup folder 📁",
+                                };
+                                if ui.button(text).clicked() {
+                                    if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                                        preset.backup_dir = Some(folder.display().to_string());
+                                    }
+                                }
+                            }
+                            ui.end_row();
                         })
                 });
 
@@ -275,21 +636,42 @@ impl RshrinkApp {
                 .clicked()
             {
                 if let Some(file_paths) = rfd::FileDialog::new().pick_files() {
-                    // Manually reset old total file size
-                    self.total_file_size = 0;
-                    self.total_new_file_size
-                        .store(self.total_file_size, Ordering::Relaxed);
-                    self.has_run_once = false;
-                    self.selected_files = file_paths
-                        .iter()
-                        .map(|path_buf| {
-                            let selected_file = SelectedFile::new(path_buf.display().to_string());
-                            self.total_file_size += selected_file.size.original;
-                            selected_file
-                        })
-                        .collect::<Vec<_>>();
+                    self.select_paths(file_paths, false);
                 }
             };
+            // Open folder explorer
+            if ui
+                .add_enabled(!self.is_running, Button::new("Select folder 📁"))
+                .clicked()
+            {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    self.select_paths(vec![folder], false);
+                }
+            };
+            // Watch folder for new files
+            match &self.watching {
+                Some(watching) => {
+                    if ui
+                        .button("Stop watching ⏹")
+                        .on_hover_text(watching.dir.display().to_string())
+                        .clicked()
+                    {
+                        watching.cancellation_token.cancel();
+                        self.watching = None;
+                    }
+                }
+                None => {
+                    if ui
+                        .add_enabled(!self.is_running, Button::new("Watch folder 👁"))
+                        .on_hover_text("Shrink new files of a folder as they appear")
+                        .clicked()
+                    {
+                        if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                            self.start_watching(ui.ctx(), folder);
+                        }
+                    }
+                }
+            }
             // Clear files
             if ui
                 .add_enabled(
@@ -301,37 +683,66 @@ impl RshrinkApp {
                 self.selected_files.clear();
                 self.has_run_once = false;
                 self.total_file_size = 0;
-                self.total_new_file_size.store(0, Ordering::Relaxed);
+                self.total_new_file_size = 0;
             };
             // Run program
-            if ui
-                .add_enabled(
-                    !self.is_running && !self.selected_files.is_empty(),
-                    Button::new("Compress files 🔨"),
-                )
-                .clicked()
-            {
+            let options_error = self.options().validate().err();
+            let mut compress = ui.add_enabled(
+                !self.is_running && !self.selected_files.is_empty() && options_error.is_none(),
+                Button::new("Compress files 🔨"),
+            );
+            if let Some(err) = &options_error {
+                compress = compress.on_disabled_hover_text(err.to_string());
+            }
+            if compress.clicked() {
                 // Clean up potential previous run before initializing a new one
-                for selected_file in &self.selected_files {
-                    let done = Arc::clone(&selected_file.done);
-                    done.store(false, Ordering::SeqCst);
-                }
-
-                self.is_running = true;
-
-                if !self.has_run_once {
-                    self.has_run_once = true;
+                for selected_file in &mut self.selected_files {
+                    selected_file.status = FileStatus::Pending;
+                    selected_file.started = false;
+                    selected_file.progress = None;
+                    selected_file.error = None;
+                    selected_file.out_file = None;
+                    selected_file.quality = None;
+                    selected_file.larger_output = None;
+                    selected_file.variants.clear();
+                    selected_file.similarity = None;
+                    selected_file.dimensions = None;
+                    selected_file.duration = None;
                 }
 
                 self.run();
             }
             if self.is_running {
-                Spinner::default().ui(ui);
+                // Stop pending jobs, already running ones finish
+                if ui
+                    .add_enabled(
+                        !self.cancellation_token.is_cancelled(),
+                        Button::new("Cancel ⏹"),
+                    )
+                    .clicked()
+                {
+                    self.cancellation_token.cancel();
+                }
+                ProgressBar::new(self.progress.fraction())
+                    .desired_width(400.)
+                    .text(self.progress.summary())
+                    .ui(ui);
             }
         });
         ui.separator();
+        if !self.watch_log.is_empty() {
+            ui.collapsing(format!("Watch log ({})", self.watch_log.len()), |ui| {
+                ScrollArea::vertical().max_height(100.).show(ui, |ui| {
+                    // Newest first
+                    for line in self.watch_log.iter().rev() {
+                        ui.label(line);
+                    }
+                });
+            });
+        }
+        self.render_presets(ui);
         ui.collapsing("Compression settings", |ui| {
-            let (mut width, mut height) = self.settings.dimensions.as_string();
+            let (mut width, mut height) = self.settings.preset.dimensions.as_string();
             // Compression Controls
             Grid::new("compression_settings_grid")
                 .num_columns(2)
@@ -339,75 +750,616 @@ impl RshrinkApp {
                 .max_col_width(100.0)
                 .show(ui, |ui| {
                     ui.label("Quality");
-                    ui.add(Slider::new(&mut self.settings.compression_quality, 1..=100));
+                    ui.add_enabled(
+                        !self.settings.preset.target_size_enabled
+                            && !self.settings.preset.min_ssim_enabled,
+                        Slider::new(&mut self.settings.preset.compression_quality, 1..=100),
+                    );
+                    ui.end_row();
+                    // Search the quality per image instead
+                    if ui
+                        .checkbox(
+                            &mut self.settings.preset.target_size_enabled,
+                            "Max file size",
+                        )
+                        .changed()
+                    {
+                        self.settings.preset.min_ssim_enabled = false;
+                    }
+                    ui.add_enabled(
+                        self.settings.preset.target_size_enabled,
+                        DragValue::new(&mut self.settings.preset.target_size_kb)
+                            .clamp_range(1..=u64::MAX)
+                            .suffix(" Kb"),
+                    );
+                    ui.end_row();
+                    ui.add_enabled(
+                        self.settings.preset.target_size_enabled,
+                        Checkbox::new(&mut self.settings.preset.target_size_resize, "Allow resize"),
+                    );
+                    ui.end_row();
+                    if ui
+                        .checkbox(&mut self.settings.preset.min_ssim_enabled, "Min SSIM")
+                        .on_hover_text("Search the lowest quality that still looks this similar to the original")
+                        .changed()
+                    {
+                        self.settings.preset.target_size_enabled = false;
+                    }
+                    ui.add_enabled(
+                        self.settings.preset.min_ssim_enabled,
+                        DragValue::new(&mut self.settings.preset.min_ssim)
+                            .clamp_range(0.0..=1.0)
+                            .speed(0.001)
+                            .fixed_decimals(3),
+                    );
+                    ui.end_row();
+                    ui.checkbox(&mut self.settings.preset.metrics, "Quality metrics")
+                        .on_hover_text("Compare every shrinked image with the original (SSIM and PSNR)");
+                    ui.end_row();
+                    // Manifests aren't written into source folders
+                    let next_to_originals = self.settings.preset.replace_originals
+                        || (self.settings.preset.output_folder_name.is_empty()
+                            && !self.settings.preset.output_folder_parent_dir_path_enabled);
+                    ui.add_enabled(
+                        !next_to_originals,
+                        Checkbox::new(&mut self.settings.preset.incremental, "Skip unchanged"),
+                    )
+                    .on_hover_text("Don't shrink files again that didn't change since the last run with these settings")
+                    .on_disabled_hover_text("Files written next to their originals are always shrinked again");
+                    ui.end_row();
+                    self.render_format_settings(ui);
+                    ui.label("If larger");
+                    ComboBox::from_id_source("larger_output")
+                        .selected_text(self.settings.preset.larger_output.name())
+                        .show_ui(ui, |ui| {
+                            for policy in LargerOutputPolicy::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.preset.larger_output,
+                                    policy,
+                                    policy.name(),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("What to do if a file ends up larger than its original");
                     ui.end_row();
                     // Resize image or keep originial size
-                    ui.checkbox(&mut self.settings.change_dimensions, "Fit dimensions");
-                    ui.horizontal(|ui| {
-                        ui.add_enabled(
-                            self.settings.change_dimensions,
-                            TextEdit::singleline(&mut width).desired_width(50.0),
-                        );
-                        ui.add_enabled(
-                            self.settings.change_dimensions,
-                            TextEdit::singleline(&mut height).desired_width(50.0),
-                        );
+                    let change_dimensions = self.settings.preset.change_dimensions;
+                    let dimensions = &mut self.settings.preset.dimensions;
+                    ui.checkbox(&mut self.settings.preset.change_dimensions, "Resize");
+                    ui.add_enabled_ui(change_dimensions, |ui| {
+                        ComboBox::from_id_source("resize_mode")
+                            .selected_text(dimensions.mode.name())
+                            .show_ui(ui, |ui| {
+                                for mode in ResizeMode::ALL {
+                                    ui.selectable_value(&mut dimensions.mode, mode, mode.name());
+                                }
+                            });
+                    });
+                    ui.end_row();
+                    ui.label("");
+                    ui.add_enabled_ui(change_dimensions, |ui| match dimensions.mode {
+                        ResizeMode::Percent => {
+                            ui.add(Slider::new(&mut dimensions.percent, 1..=400).suffix(" %"));
+                        }
+                        ResizeMode::LongEdge => {
+                            ui.add(
+                                DragValue::new(&mut dimensions.long_edge)
+                                    .clamp_range(1..=8000)
+                                    .suffix(" px"),
+                            );
+                        }
+                        _ => {
+                            ui.horizontal(|ui| {
+                                TextEdit::singleline(&mut width).desired_width(50.0).ui(ui);
+                                TextEdit::singleline(&mut height).desired_width(50.0).ui(ui);
+                            });
+                        }
                     });
                     ui.end_row();
                 });
-            if let Err(err) = self
+            self.dimensions_error = self
                 .settings
+                .preset
                 .dimensions
                 .save_dimensions_from_string((width, height))
-            {
-                eprintln!("Error saving dimensions! {}", err)
+                .err();
+            if let Some(err) = &self.dimensions_error {
+                ui.colored_label(Color32::RED, err.to_string());
             }
         });
-    }
-    pub fn render_main(&mut self, ui: &mut Ui, last_folder: &mut str) {
-        if !self.selected_files.is_empty() {
-            ScrollArea::vertical().show(ui, |ui| {
-                let mut files_to_remove_indexes = Vec::new();
-                // Determine if compression finished
-                let mut all_done = true;
-                for (i, selected_file) in self.selected_files.iter().enumerate() {
-                    // For coloring columns
-                    // egui::Frame::window(&(*ctx.style()).clone())..show(ui, |ui| {
-                    // ui.label("Label with red background");
-                    // });
-                    let (done, remove_file) = render_file(
-                        ui,
-                        selected_file,
-                        self.is_running,
-                        self.has_run_once,
-                        last_folder,
+        ui.collapsing("Responsive sizes", |ui| {
+            let preset = &mut self.settings.preset;
+            ui.label("Every image is written in each of these widths instead of resizing it once");
+            let mut removed = None;
+            for (i, variant) in preset.variants.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut variant.width)
+                            .clamp_range(1..=8000)
+                            .suffix(" px"),
                     );
-                    // If one file hasn't finished compressing, we don't care anymore
-                    if all_done && !done {
-                        all_done = false
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(i);
                     }
-                    if !self.is_running && remove_file {
-                        files_to_remove_indexes.push(i);
-                        // Decrease total file size manually
-                        self.total_file_size -= selected_file.size.original;
-                        self.total_new_file_size.fetch_sub(
-                            selected_file.size.new.load(Ordering::Relaxed),
-                            Ordering::Relaxed,
-                        );
+                });
+            }
+            if let Some(i) = removed {
+                preset.variants.remove(i);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Add size ➕").clicked() {
+                    let width = preset.variants.last().map_or(640, |last| last.width * 2);
+                    if let Ok(variant) = Dimensions::width(width.min(8000)) {
+                        preset.variants.push(variant);
                     }
                 }
-                if all_done {
-                    self.is_running = false;
+                ui.add_enabled_ui(!preset.variants.is_empty(), |ui| {
+                    ComboBox::from_label("Describe in")
+                        .selected_text(preset.srcset.name())
+                        .show_ui(ui, |ui| {
+                            for srcset in Srcset::ALL {
+                                ui.selectable_value(&mut preset.srcset, srcset, srcset.name());
+                            }
+                        });
+                });
+            });
+        });
+        ui.collapsing("Metadata", |ui| {
+            let metadata = &mut self.settings.preset.metadata;
+            ui.checkbox(&mut metadata.auto_orient, "Rotate according to orientation");
+            ui.checkbox(&mut metadata.strip_gps_only, "Only remove GPS location")
+                .on_hover_text("Keep all other metadata");
+            ui.add_enabled_ui(!metadata.strip_gps_only, |ui| {
+                ui.checkbox(&mut metadata.keep_icc, "Keep color profile");
+                ui.add_enabled(
+                    !metadata.keep_icc,
+                    Checkbox::new(&mut metadata.convert_to_srgb, "Convert to sRGB"),
+                );
+                ui.checkbox(&mut metadata.keep_copyright, "Keep copyright and author");
+            });
+        });
+    }
+    /// Dropdown to apply a preset and controls to manage them
+    fn render_presets(&mut self, ui: &mut Ui) {
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            ComboBox::from_id_source("preset")
+                .selected_text(settings.selected_preset.as_deref().unwrap_or("Custom"))
+                .show_ui(ui, |ui| {
+                    for (name, preset) in &settings.presets {
+                        let selected = settings.selected_preset.as_ref() == Some(name);
+                        if ui.selectable_label(selected, name).clicked() {
+                            settings.preset = preset.clone();
+                            settings.selected_preset = Some(name.clone());
+                        }
+                    }
+                    // Project presets can only be changed in the config file
+                    if let Some((_, config)) = &self.project_config {
+                        ui.separator();
+                        for (name, preset) in &config.presets {
+                            if ui
+                                .selectable_label(false, format!("{name} (project)"))
+                                .clicked()
+                            {
+                                settings.preset = preset.clone();
+                                settings.selected_preset = None;
+                            }
+                        }
+                    }
+                });
+            // Overwrite the selected preset with the current settings
+            if let Some(name) = &settings.selected_preset {
+                if ui.button("Save 💾").clicked() {
+                    settings
+                        .presets
+                        .insert(name.clone(), settings.preset.clone());
                 }
-                for i in files_to_remove_indexes {
-                    self.selected_files.remove(i);
+            }
+            if let Some((path, _)) = &self.project_config {
+                ui.label(RichText::new("Project config 📄").weak())
+                    .on_hover_text(path.display().to_string());
+            }
+        });
+        if let Some(err) = &self.config_error {
+            ui.colored_label(Color32::RED, err.to_string());
+        }
+        ui.collapsing("Manage presets", |ui| {
+            ui.horizontal(|ui| {
+                TextEdit::singleline(&mut self.preset_name)
+                    .hint_text("Name")
+                    .desired_width(120.0)
+                    .ui(ui);
+                let name = self.preset_name.trim().to_owned();
+                let name_available = !name.is_empty() && !settings.presets.contains_key(&name);
+                if ui
+                    .add_enabled(name_available, Button::new("New ➕"))
+                    .on_hover_text("Save the current settings under this name")
+                    .clicked()
+                {
+                    settings
+                        .presets
+                        .insert(name.clone(), settings.preset.clone());
+                    settings.selected_preset = Some(name);
+                    self.preset_name.clear();
+                }
+                let selected = settings.selected_preset.clone();
+                if ui
+                    .add_enabled(
+                        name_available && selected.is_some(),
+                        Button::new("Rename ✏"),
+                    )
+                    .clicked()
+                {
+                    if let Some(preset) = selected.and_then(|old| settings.presets.remove(&old)) {
+                        settings.presets.insert(name.clone(), preset);
+                        settings.selected_preset = Some(name);
+                        self.preset_name.clear();
+                    }
+                }
+                if ui
+                    .add_enabled(settings.selected_preset.is_some(), Button::new("Delete 🗑"))
+                    .clicked()
+                {
+                    if let Some(name) = settings.selected_preset.take() {
+                        settings.presets.remove(&name);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!settings.presets.is_empty(), Button::new("Export 📤"))
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("JSON", &["json"])
+                        .set_file_name("rshrink-presets.json")
+                        .save_file()
+                    {
+                        self.preset_error = export_presets(&path, &settings.presets).err();
+                    }
+                }
+                if ui.button("Import 📥").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("JSON", &["json"])
+                        .pick_file()
+                    {
+                        // Presets with the same name are replaced by the imported ones
+                        self.preset_error = match import_presets(&path) {
+                            Ok(presets) => {
+                                settings.presets.extend(presets);
+                                None
+                            }
+                            Err(err) => Some(err),
+                        };
+                    }
                 }
             });
-        } else {
+            if let Some(err) = &self.preset_error {
+                ui.colored_label(Color32::RED, err.to_string());
+            }
+        });
+    }
+    /// Output format and the settings of the selected format, rendered into the settings grid
+    fn render_format_settings(&mut self, ui: &mut Ui) {
+        let format = &mut self.settings.preset.format;
+        ui.label("Format");
+        ComboBox::from_id_source("output_format")
+            .selected_text(format.format.name())
+            .show_ui(ui, |ui| {
+                for output_format in OutputFormat::ALL {
+                    ui.selectable_value(&mut format.format, output_format, output_format.name());
+                }
+            });
+        ui.end_row();
+        if !format.format.supports_alpha() {
+            ui.label("Background");
+            ui.color_edit_button_srgb(&mut format.background)
+                .on_hover_text("Transparent areas are flattened against this color");
+            ui.end_row();
+        }
+        match format.format {
+            OutputFormat::WebP => {
+                ui.checkbox(&mut format.webp_lossless, "Lossless");
+                ui.end_row();
+            }
+            OutputFormat::Avif => {
+                ui.label("Speed");
+                ui.add(Slider::new(&mut format.avif_speed, 0..=10));
+                ui.end_row();
+            }
+            OutputFormat::Jxl => {
+                ui.label("Effort");
+                ui.add(Slider::new(&mut format.jxl_effort, 1..=9));
+                ui.end_row();
+            }
+            _ => {}
+        }
+        // JPEG inputs keep their format
+        if matches!(format.format, OutputFormat::Jpeg | OutputFormat::Original) {
+            ui.label("JPEG subsampling");
+            ComboBox::from_id_source("jpeg_subsampling")
+                .selected_text(format.jpeg_subsampling.name())
+                .show_ui(ui, |ui| {
+                    for subsampling in Subsampling::ALL {
+                        ui.selectable_value(
+                            &mut format.jpeg_subsampling,
+                            subsampling,
+                            subsampling.name(),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text("4:4:4 keeps sharp colored edges and text, 4:2:0 is smallest");
+            ui.end_row();
+            ui.checkbox(&mut format.jpeg_progressive, "Progressive");
+            ui.checkbox(&mut format.jpeg_optimize_coding, "Optimize coding")
+                .on_hover_text("Compute optimal Huffman tables");
+            ui.end_row();
+            ui.checkbox(&mut format.jpeg_arithmetic_coding, "Arithmetic coding")
+                .on_hover_text("Smaller, but most browsers can't display it");
+            ui.end_row();
+            ui.label("Colorspace");
+            ComboBox::from_id_source("jpeg_colorspace")
+                .selected_text(format.jpeg_colorspace.name())
+                .show_ui(ui, |ui| {
+                    for colorspace in JpegColorspace::ALL {
+                        ui.selectable_value(
+                            &mut format.jpeg_colorspace,
+                            colorspace,
+                            colorspace.name(),
+                        );
+                    }
+                });
+            ui.end_row();
+        }
+        // PNG inputs keep their format
+        if matches!(format.format, OutputFormat::Png | OutputFormat::Original) {
+            ui.label("PNG");
+            ComboBox::from_id_source("png_mode")
+                .selected_text(format.png_mode.name())
+                .show_ui(ui, |ui| {
+                    for png_mode in PngMode::ALL {
+                        ui.selectable_value(&mut format.png_mode, png_mode, png_mode.name());
+                    }
+                })
+                .response
+                .on_hover_text("Auto keeps images with few colors lossless");
+            ui.end_row();
+            if format.png_mode != PngMode::Lossless {
+                ui.label("Colors");
+                ui.add(Slider::new(&mut format.png_colors, 2..=256));
+                ui.end_row();
+                ui.checkbox(&mut format.png_dither, "Dither");
+                ui.checkbox(&mut format.png_reduce_depth, "8 bit")
+                    .on_hover_text("Store quantized 16 bit images with 8 bits per channel");
+                ui.end_row();
+            }
+            ui.label("Compression level");
+            ui.add(Slider::new(&mut format.png_compression_level, 0..=9));
+            ui.end_row();
+        }
+    }
+    pub fn render_main(&mut self, ui: &mut Ui) {
+        if self.selected_files.is_empty() {
             ui.centered_and_justified(|ui| {
-                ui.label("Select files or drop them here");
+                ui.label("Select files or folders or drop them here");
+            });
+            return;
+        }
+
+        let visible = self.visible_files();
+        let selected = visible
+            .iter()
+            .copied()
+            .filter(|&i| self.selected_files[i].selected)
+            .collect::<Vec<_>>();
+        let mut remove_selected = false;
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ui.add(
+                TextEdit::singleline(&mut self.filter)
+                    .hint_text("Part of the path")
+                    .desired_width(150.),
+            );
+            ui.checkbox(&mut self.settings.group_by_folder, "Group by folder");
+            ui.with_layout(Layout::right_to_left(), |ui| {
+                if ui
+                    .add_enabled(
+                        self.has_run_once && !self.is_running,
+                        Button::new("Export report 📄"),
+                    )
+                    .on_hover_text("Sizes, dimensions, quality, time and errors of every file")
+                    .clicked()
+                {
+                    self.export_report();
+                }
+                if let Some(err) = &self.report_error {
+                    ui.colored_label(Color32::RED, err.to_string());
+                }
+                remove_selected = ui
+                    .add_enabled(
+                        !self.is_running && !selected.is_empty(),
+                        Button::new(format!("Remove selected ({}) ❌", selected.len())),
+                    )
+                    .clicked();
+            });
+        });
+
+        let mut files_to_remove_indexes = Vec::new();
+        let mut preview_file = None;
+        ScrollArea::both().show(ui, |ui| {
+            let (remove, preview) = match self.settings.group_by_folder {
+                true => {
+                    let mut folders = BTreeMap::<String, Vec<usize>>::new();
+                    for &i in &visible {
+                        folders
+                            .entry(self.selected_files[i].parent_folder.clone())
+                            .or_default()
+                            .push(i);
+                    }
+                    let mut remove = Vec::new();
+                    let mut preview = None;
+                    for (folder, indexes) in folders {
+                        CollapsingHeader::new(format!("{folder} ({})", indexes.len()))
+                            .id_source(&folder)
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                let (folder_remove, folder_preview) =
+                                    self.render_table(ui, &folder, &indexes);
+                                remove.extend(folder_remove);
+                                preview = preview.or(folder_preview);
+                            });
+                    }
+                    (remove, preview)
+                }
+                false => self.render_table(ui, "files", &visible),
+            };
+            files_to_remove_indexes = remove;
+            preview_file = preview;
+        });
+        if remove_selected {
+            files_to_remove_indexes.extend(selected);
+        }
+        if !self.is_running {
+            self.remove_files(files_to_remove_indexes);
+        }
+        if let Some(i) = preview_file {
+            self.open_preview(i);
+        }
+    }
+
+    /// Render the files at `indexes` as a table, returns the files to remove and to preview
+    fn render_table(
+        &mut self,
+        ui: &mut Ui,
+        id: &str,
+        indexes: &[usize],
+    ) -> (Vec<usize>, Option<usize>) {
+        let mut files_to_remove_indexes = Vec::new();
+        let mut preview_file = None;
+        let columns = Column::ALL
+            .into_iter()
+            // Already the section's title
+            .filter(|column| !(self.settings.group_by_folder && *column == Column::Folder))
+            .collect::<Vec<_>>();
+        Grid::new(id)
+            .striped(true)
+            .num_columns(columns.len() + 3)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                let mut all_selected = indexes.iter().all(|&i| self.selected_files[i].selected);
+                if ui.checkbox(&mut all_selected, "").changed() {
+                    for &i in indexes {
+                        self.selected_files[i].selected = all_selected;
+                    }
+                }
+                for &column in &columns {
+                    let arrow = match (self.settings.sort_column, self.settings.sort_descending) {
+                        (Some(sorted), false) if sorted == column => " ⏶",
+                        (Some(sorted), true) if sorted == column => " ⏷",
+                        _ => "",
+                    };
+                    let header = RichText::new(format!("{}{arrow}", column.name())).strong();
+                    if ui.add(Button::new(header).frame(false)).clicked() {
+                        // Clicking the sorted column again reverses the order
+                        self.settings.sort_descending = self.settings.sort_column == Some(column)
+                            && !self.settings.sort_descending;
+                        self.settings.sort_column = Some(column);
+                    }
+                }
+                ui.label("");
+                ui.label("");
+                ui.end_row();
+
+                for &i in indexes {
+                    ui.checkbox(&mut self.selected_files[i].selected, "");
+                    let selected_file = &self.selected_files[i];
+                    for &column in &columns {
+                        render_cell(ui, column, selected_file, self.is_running);
+                    }
+                    ui.horizontal(|ui| render_details(ui, selected_file));
+                    ui.horizontal(|ui| {
+                        // Videos can't be previewed
+                        if ui
+                            .add_enabled(
+                                !is_video(Path::new(&selected_file.path))
+                                    && selected_file.original_file().is_some(),
+                                Button::new("🔍"),
+                            )
+                            .on_hover_text("Preview")
+                            .on_disabled_hover_text(
+                                match is_video(Path::new(&selected_file.path)) {
+                                    true => "Videos can't be previewed",
+                                    false => "The original was replaced without a backup",
+                                },
+                            )
+                            .clicked()
+                        {
+                            preview_file = Some(i);
+                        }
+                        if ui
+                            .add_enabled(!self.is_running, Button::new("❌"))
+                            .clicked()
+                        {
+                            files_to_remove_indexes.push(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        (files_to_remove_indexes, preview_file)
+    }
+
+    /// Indexes of the files whose path contains the filter, sorted by the selected column
+    fn visible_files(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        let mut indexes = (0..self.selected_files.len())
+            .filter(|&i| self.selected_files[i].path.to_lowercase().contains(&filter))
+            .collect::<Vec<_>>();
+        if let Some(column) = self.settings.sort_column {
+            // Stable, equal files stay in the order they were selected
+            indexes.sort_by(|&a, &b| {
+                let ordering = column.compare(&self.selected_files[a], &self.selected_files[b]);
+                match self.settings.sort_descending {
+                    true => ordering.reverse(),
+                    false => ordering,
+                }
             });
         }
+        indexes
+    }
+
+    /// Write a report of all selected files to a file picked by the user
+    fn export_report(&mut self) {
+        let mut dialog = rfd::FileDialog::new().set_file_name("rshrink-report.html");
+        for format in ReportFormat::ALL {
+            dialog = dialog.add_filter(format.name(), &[format.extension()]);
+        }
+        if let Some(path) = dialog.save_file() {
+            let files = self
+                .selected_files
+                .iter()
+                .map(SelectedFile::report)
+                .collect();
+            let format = ReportFormat::from_path(&path).unwrap_or_default();
+            self.report_error = Report::new(files).write(&path, format).err();
+        }
+    }
+
+    /// Remove the files at `indexes` and their sizes from the totals
+    fn remove_files(&mut self, mut indexes: Vec<usize>) {
+        indexes.sort_unstable();
+        indexes.dedup();
+        // Back to front, so the remaining indexes stay valid
+        for i in indexes.into_iter().rev() {
+            let selected_file = self.selected_files.remove(i);
+            self.total_file_size -= selected_file.size.original;
+            self.total_new_file_size = self
+                .total_new_file_size
+                .saturating_sub(selected_file.size.new);
+        }
     }
 
     pub fn detect_files_being_dropped(&mut self, ctx: &egui::Context) {
@@ -430,156 +1382,575 @@ impl RshrinkApp {
         }
         // Collect dropped files
         if !ctx.input().raw.dropped_files.is_empty() {
-            // Manually reset old total file size
-            self.total_file_size = 0;
-            self.total_new_file_size
-                .store(self.total_file_size, Ordering::Relaxed);
-            self.has_run_once = false;
-            self.selected_files = ctx
+            let paths = ctx
                 .input()
                 .raw
                 .dropped_files
                 .iter()
-                .filter(|dropped_file| match &dropped_file.path {
-                    Some(file) => {
-                        let regex = Regex::new(DEFAULT_REGEX);
-                        match regex {
-                            Ok(regex) => regex.is_match(&file.display().to_string()),
-                            Err(_) => false,
-                        }
-                    }
-                    None => false,
-                })
-                .map(|dropped_file| match &dropped_file.path {
-                    Some(file_path) => {
-                        let selected_file = SelectedFile::new(file_path.display().to_string());
-                        self.total_file_size += selected_file.size.original;
-                        selected_file
-                    }
-                    None => SelectedFile::new("???".to_owned()),
-                })
-                .collect::<_>();
-        }
-    }
-
-    fn run(&self) {
-        let Settings {
-            output_folder_parent_dir_path_enabled: _,
-            output_folder_name,
-            output_folder_parent_dir_path,
-            change_dimensions,
-            dimensions,
-            compression_quality,
-            ..
-        } = &self.settings;
-        let dims = Arc::new(match change_dimensions {
-            true => Some(dimensions.clone()),
-            false => None,
+                .filter_map(|dropped_file| dropped_file.path.clone())
+                .collect::<Vec<_>>();
+            self.select_paths(paths, true);
+        }
+    }
+
+    /// Replace the selected files, folders are searched recursively for supported files. Files
+    /// not matching the file regex are only skipped if `filter_files` is set
+    fn select_paths(&mut self, paths: Vec<PathBuf>, filter_files: bool) {
+        // Manually reset old total file size
+        self.total_file_size = 0;
+        self.total_new_file_size = 0;
+        self.has_run_once = false;
+        self.selected_files.clear();
+
+        if let Some(path) = paths.first() {
+            self.load_project_config(path);
+        }
+        let file_regex = self
+            .project_config
+            .as_ref()
+            .and_then(|(_, config)| config.file_regex.clone())
+            .unwrap_or_else(|| String::from(DEFAULT_REGEX));
+        let options = self.settings.preset.options();
+        for path in paths {
+            if !path.is_dir() {
+                if filter_files
+                    && !parse_file(&file_regex, &path.display().to_string()).unwrap_or(false)
+                {
+                    continue;
+                }
+                self.selected_files
+                    .push(SelectedFile::new(path.display().to_string()));
+                continue;
+            }
+            // Results of a previous run are inside the folder if no other parent is set
+            match list_files_recursive(&path, &file_regex, options.excluded_dir(&path).as_deref()) {
+                Ok(files) => self.selected_files.extend(files.into_iter().map(|file| {
+                    SelectedFile::new(file.display().to_string()).in_tree(path.clone())
+                })),
+                Err(err) => {
+                    // List the folder itself, so the user sees why it's empty
+                    let mut selected_file = SelectedFile::new(path.display().to_string());
+                    selected_file.status = FileStatus::Failed;
+                    selected_file.error = Some(err);
+                    self.selected_files.push(selected_file);
+                }
+            }
+        }
+        self.total_file_size = self
+            .selected_files
+            .iter()
+            .map(|selected_file| selected_file.size.original)
+            .sum();
+    }
+
+    /// Look for a project config next to `path`, its defaults replace the current settings
+    /// whenever a different config is found
+    fn load_project_config(&mut self, path: &Path) {
+        let dir = match path.is_dir() {
+            true => path,
+            false => path.parent().unwrap_or(path),
+        };
+        let previous = self.project_config.take().map(|(path, _)| path);
+        self.config_error = None;
+        self.project_config = match Config::discover(dir) {
+            Ok(config) => config,
+            Err(err) => {
+                self.config_error = Some(err);
+                None
+            }
+        };
+        if let Some((path, config)) = &self.project_config {
+            if previous.as_ref() != Some(path) {
+                self.settings.preset = config.defaults.clone();
+                self.settings.selected_preset = None;
+            }
+        }
+    }
+
+    /// Decode the original and compressed version of a file in the background, on a thread of
+    /// its own so a running batch doesn't hold it up
+    fn open_preview(&mut self, index: usize) {
+        let selected_file = &self.selected_files[index];
+        let in_file = match selected_file.original_file() {
+            Some(in_file) => in_file,
+            // The preview button is disabled then
+            None => return,
+        };
+        let out_file = selected_file.out_file.clone();
+        let options = self.settings.preset.options().magick_options();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let images = PreviewImages::load(&in_file, out_file.as_deref(), &options);
+            // Preview might have been closed in the meantime
+            let _ = sender.send(images);
         });
-        let mut prev_dir = String::new();
-        for selected_file in &self.selected_files {
-            let selected_file = selected_file.clone();
-            println!(
-                "{},{:?}",
-                selected_file.parent_folder, output_folder_parent_dir_path
-            );
-            let out_folder = match output_folder_parent_dir_path {
-                Some(path) => format!("{}/{}", path, output_folder_name),
-                None => format!("{}/{}", selected_file.parent_folder, output_folder_name),
-            };
-            if out_folder != prev_dir {
-                if let Err(err) = create_dir_if_not_exists(&out_folder) {
-                    eprintln!("Failed to create folder! {}", err)
+        self.preview = Some(Preview {
+            name: selected_file.name.clone(),
+            loading: Some(receiver),
+            textures: None,
+            error: None,
+            split: 0.5,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            open: true,
+        });
+    }
+
+    fn render_preview(&mut self, ctx: &Context) {
+        let preview = match &mut self.preview {
+            Some(preview) => preview,
+            None => return,
+        };
+        // Upload the decoded images once they are ready
+        if let Some(loading) = &preview.loading {
+            match loading.try_recv() {
+                Ok(Ok(images)) => {
+                    preview.textures = Some(PreviewTextures {
+                        original: load_texture(ctx, "preview_original", &images.original),
+                        compressed: load_texture(ctx, "preview_compressed", &images.compressed),
+                        original_size: images.original_size,
+                        compressed_size: images.compressed_size,
+                        from_output_file: images.from_output_file,
+                    });
+                    preview.loading = None;
                 }
+                Ok(Err(err)) => {
+                    preview.error = Some(err);
+                    preview.loading = None;
+                }
+                Err(TryRecvError::Empty) => ctx.request_repaint(),
+                Err(TryRecvError::Disconnected) => preview.loading = None,
             }
-            let out_file_path =
-                // Remove "/" at the end
-                match out_folder[..out_folder.len() - 1] == selected_file.parent_folder {
-                    // Results in 2 "/", if output_folder_name is empty, but that shouldn't be a problem
-                    true => format!("{}/min-{}", out_folder, selected_file.name),
-                    false => format!("{}/{}", out_folder, selected_file.name),
+        }
+
+        let mut open = preview.open;
+        Window::new(format!("Preview {}", preview.name))
+            .id(Id::new("preview"))
+            .open(&mut open)
+            .default_size(Vec2::new(600., 400.))
+            .resizable(true)
+            .show(ctx, |ui| {
+                if let Some(err) = &preview.error {
+                    ui.colored_label(Color32::RED, err.to_string());
+                    return;
+                }
+                let textures = match &preview.textures {
+                    Some(textures) => textures,
+                    None => {
+                        ui.centered_and_justified(|ui| Spinner::default().ui(ui));
+                        return;
+                    }
                 };
-            let dims = Arc::clone(&dims);
-            let compression_quality = *compression_quality;
-            let done = Arc::clone(&selected_file.done);
-            let new_filesize = Arc::clone(&selected_file.size.new);
-
-            // Reset total file size
-            self.total_new_file_size.store(0, Ordering::Relaxed);
-            let total_new_file_size = Arc::clone(&self.total_new_file_size);
-
-            self.thread_pool.execute(move || {
-                if let Err(err) = perform_magick(
-                    &selected_file.path,
-                    &out_file_path,
-                    dims,
-                    compression_quality,
-                    false,
-                ) {
-                    eprintln!("Failed to shrink file {}! : {}", selected_file.path, err)
-                } else {
-                    // Read file metadata to determine new file size
-                    match File::open(&out_file_path) {
-                        Ok(file) => match File::metadata(&file) {
-                            Ok(metadata) => {
-                                let file_size = metadata.len();
-                                // Store the indiviual files new size
-                                new_filesize.store(file_size, Ordering::Relaxed);
-                                // Store the overall new file size
-                                total_new_file_size.fetch_add(file_size, Ordering::Relaxed);
-                            }
-                            Err(err) => {
-                                eprintln!("Failed to read the new file's metadata! {}", err)
-                            }
+                ui.horizontal(|ui| {
+                    ui.label(format!("Original: {} Kb", textures.original_size / 1024));
+                    ui.add(
+                        Slider::new(&mut preview.split, 0.0..=1.0)
+                            .show_value(false)
+                            .text("Split"),
+                    );
+                    ui.label(format!(
+                        "{}: {} Kb ({}%)",
+                        match textures.from_output_file {
+                            true => "Compressed",
+                            false => "Current settings",
                         },
-                        Err(err) => eprintln!("Failed to read new file size! {}", err),
+                        textures.compressed_size / 1024,
+                        round_percent(textures.compressed_size, textures.original_size)
+                    ));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("1:1").clicked() {
+                        preview.zoom = 1.0;
+                        preview.pan = Vec2::ZERO;
+                    }
+                    if ui.button("Fit").clicked() {
+                        let [width, height] = textures.original.size();
+                        let available = ui.available_size();
+                        preview.zoom = (available.x / width as f32)
+                            .min(available.y / height as f32)
+                            .clamp(MIN_ZOOM, MAX_ZOOM);
+                        preview.pan = Vec2::ZERO;
                     }
+                    ui.label(format!("{}%", (preview.zoom * 100.).round()));
+                });
+                ui.separator();
+
+                // Drag to pan, scroll to zoom
+                let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
+                if response.dragged() {
+                    preview.pan += response.drag_delta();
                 }
-                // Complete the job for the UI
-                done.store(true, Ordering::Relaxed);
+                if response.hovered() {
+                    let scroll = ui.input().scroll_delta.y;
+                    if scroll != 0. {
+                        preview.zoom =
+                            (preview.zoom * (1. + scroll * 0.002)).clamp(MIN_ZOOM, MAX_ZOOM);
+                    }
+                }
+
+                // Both images are drawn over the original's area, so they line up after resizing
+                let [width, height] = textures.original.size();
+                let image_rect = Rect::from_center_size(
+                    rect.center() + preview.pan,
+                    Vec2::new(width as f32, height as f32) * preview.zoom,
+                );
+                let split_x = image_rect.left() + image_rect.width() * preview.split;
+                let painter = ui.painter_at(rect);
+                painter.image(
+                    textures.original.id(),
+                    Rect::from_min_max(image_rect.min, pos2(split_x, image_rect.max.y)),
+                    Rect::from_min_max(pos2(0., 0.), pos2(preview.split, 1.)),
+                    Color32::WHITE,
+                );
+                painter.image(
+                    textures.compressed.id(),
+                    Rect::from_min_max(pos2(split_x, image_rect.min.y), image_rect.max),
+                    Rect::from_min_max(pos2(preview.split, 0.), pos2(1., 1.)),
+                    Color32::WHITE,
+                );
+                painter.vline(split_x, rect.y_range(), Stroke::new(1., Color32::WHITE));
             });
-            prev_dir = out_folder;
+        preview.open = open;
+        if !preview.open {
+            self.preview = None;
+        }
+    }
+
+    fn run(&mut self) {
+        let options = self.options();
+        let jobs = self
+            .selected_files
+            .iter()
+            .enumerate()
+            .map(|(index, selected_file)| {
+                let path = PathBuf::from(&selected_file.path);
+                match &selected_file.root {
+                    Some(root) => Job::in_tree(path, index, root, &options),
+                    None => Job::new(path, index, &options),
+                }
+            })
+            .collect();
+        let pipeline = match Pipeline::from_jobs(jobs, options) {
+            Ok(pipeline) => pipeline,
+            // Compressing is disabled while the options are invalid
+            Err(_) => return,
+        };
+
+        // Reset total file size
+        self.total_new_file_size = 0;
+        self.start(pipeline, 0);
+    }
+
+    /// Options of the current settings, knowing the selected preset's name
+    fn options(&self) -> Options {
+        Options {
+            preset_name: self.settings.selected_preset.clone(),
+            ..self.settings.preset.options()
+        }
+    }
+
+    /// Dispatch `pipeline`, its jobs belong to the selected files starting at `first`
+    fn start(&mut self, pipeline: Pipeline, first: usize) {
+        self.is_running = true;
+        self.has_run_once = true;
+        self.events_offset = first;
+        self.progress = Progress::new();
+        self.cancellation_token = pipeline.cancellation_token();
+        for (selected_file, job) in self.selected_files[first..].iter_mut().zip(pipeline.jobs()) {
+            selected_file.backup_file = job.backup_file.clone();
+        }
+        self.events = Some(pipeline.run(&self.thread_pool));
+    }
+
+    /// Watch `folder` with the current settings, files already inside are left alone
+    fn start_watching(&mut self, ctx: &Context, folder: PathBuf) {
+        self.load_project_config(&folder);
+        let file_regex = self
+            .project_config
+            .as_ref()
+            .and_then(|(_, config)| config.file_regex.clone())
+            .unwrap_or_else(|| String::from(DEFAULT_REGEX));
+        let options = self.options();
+        match Watcher::new(&folder, &file_regex, true, options.clone()) {
+            Ok(watcher) => {
+                let cancellation_token = CancellationToken::new();
+                let ctx = ctx.clone();
+                let jobs = watcher.spawn(cancellation_token.clone(), move || ctx.request_repaint());
+                self.watch_log
+                    .push(format!("Watching {}", folder.display()));
+                self.watching = Some(Watching {
+                    dir: folder,
+                    options,
+                    jobs,
+                    cancellation_token,
+                    queue: Vec::new(),
+                });
+            }
+            Err(err) => self
+                .watch_log
+                .push(format!("Failed to watch {}! {err}", folder.display())),
+        }
+    }
+
+    /// Queue jobs of new files found by the watcher, they are started once no batch is running
+    fn poll_watcher(&mut self) {
+        let watching = match &mut self.watching {
+            Some(watching) => watching,
+            None => return,
+        };
+        while let Ok(jobs) = watching.jobs.try_recv() {
+            match jobs {
+                Ok(jobs) => watching.queue.extend(jobs),
+                Err(err) => self
+                    .watch_log
+                    .push(format!("Failed to read directory! {err}")),
+            }
+        }
+        if self.is_running || watching.queue.is_empty() {
+            return;
+        }
+
+        let jobs = std::mem::take(&mut watching.queue);
+        let pipeline = match Pipeline::from_jobs(jobs, watching.options.clone()) {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                self.watch_log
+                    .push(format!("Failed to shrink new files! {err}"));
+                return;
+            }
+        };
+        let first = self.selected_files.len();
+        for job in pipeline.jobs() {
+            let selected_file =
+                SelectedFile::new(job.in_file.display().to_string()).in_tree(watching.dir.clone());
+            self.total_file_size += selected_file.size.original;
+            self.selected_files.push(selected_file);
+        }
+        self.start(pipeline, first);
+    }
+
+    /// Apply progress and results of finished jobs to their files
+    fn poll_events(&mut self, ctx: &Context) {
+        let events = match &self.events {
+            Some(events) => events,
+            None => return,
+        };
+        loop {
+            let event = match events.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => {
+                    // Keep polling while jobs are running
+                    ctx.request_repaint();
+                    break;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    // All jobs are done
+                    self.events = None;
+                    self.is_running = false;
+                    break;
+                }
+            };
+            self.progress.update(&event);
+            match event {
+                Event::Queued { .. } => {}
+                Event::Started { index } => {
+                    self.selected_files[self.events_offset + index].started = true;
+                }
+                Event::Progress { index, fraction } => {
+                    self.selected_files[self.events_offset + index].progress = Some(fraction);
+                }
+                Event::Finished(job_result) => {
+                    let selected_file =
+                        &mut self.selected_files[self.events_offset + job_result.index];
+                    selected_file.duration = Some(job_result.duration);
+                    // Complete the job for the UI
+                    selected_file.status = match job_result.outcome {
+                        Outcome::Done(shrinked) => {
+                            selected_file.apply(shrinked);
+                            // Store the overall new file size
+                            self.total_new_file_size += selected_file.size.new;
+                            FileStatus::Done
+                        }
+                        Outcome::UpToDate(shrinked) => {
+                            selected_file.apply(shrinked);
+                            self.total_new_file_size += selected_file.size.new;
+                            FileStatus::UpToDate
+                        }
+                        Outcome::Failed(err) => {
+                            selected_file.error = Some(err);
+                            FileStatus::Failed
+                        }
+                        Outcome::Cancelled => FileStatus::Cancelled,
+                        Outcome::Exists => {
+                            // Counted as kept at its original size
+                            selected_file.size.new = selected_file.size.original;
+                            self.total_new_file_size += selected_file.size.new;
+                            FileStatus::Exists
+                        }
+                    };
+                    if self.watching.is_some() {
+                        self.watch_log
+                            .push(match (&selected_file.status, &selected_file.error) {
+                                (FileStatus::Failed, Some(err)) => {
+                                    format!("{}: {err}", selected_file.path)
+                                }
+                                (status, _) => format!(
+                                    "{}: {} Kb -> {} Kb{}",
+                                    selected_file.path,
+                                    selected_file.size.original / 1024,
+                                    selected_file.size.new / 1024,
+                                    match status {
+                                        FileStatus::UpToDate => ", up to date",
+                                        FileStatus::Cancelled => ", cancelled",
+                                        FileStatus::Exists => ", output already exists",
+                                        _ => "",
+                                    }
+                                ),
+                            });
+                    }
+                }
+            }
         }
     }
 }
 
-fn render_file(
-    ui: &mut Ui,
-    selected_file: &SelectedFile,
-    is_running: bool,
-    has_run_once: bool,
-    _last_folder: &mut str,
-) -> (bool, bool) {
-    let mut remove_file = false;
-    let done = match is_running {
-        true => selected_file.done.load(Ordering::Relaxed),
-        false => false,
+fn render_cell(ui: &mut Ui, column: Column, selected_file: &SelectedFile, is_running: bool) {
+    // Sizes are only known once the file was shrinked
+    let has_result = selected_file.has_result();
+    let dimensions = |dimensions: Option<(usize, usize)>| match dimensions {
+        Some((width, height)) => format!("{width}x{height}"),
+        None => String::from("–"),
     };
-    ui.horizontal(|ui| {
-        ui.label(RichText::new(&selected_file.name).strong())
-            .on_hover_text_at_pointer(&selected_file.path);
-        ui.with_layout(Layout::right_to_left(), |ui| {
-            if ui.add_enabled(!is_running, Button::new("❌")).clicked() {
-                remove_file = true
+    match column {
+        Column::Folder => {
+            ui.label(&selected_file.parent_folder);
+        }
+        Column::Name => {
+            ui.label(RichText::new(&selected_file.name).strong())
+                .on_hover_text_at_pointer(&selected_file.path);
+        }
+        Column::Size => {
+            ui.label(format!("{} Kb", selected_file.size.original / 1024));
+        }
+        Column::NewSize => {
+            ui.label(match has_result {
+                true => format!("{} Kb", selected_file.size.new / 1024),
+                false => String::from("–"),
+            });
+        }
+        Column::Savings => {
+            ui.label(match has_result {
+                true => format!("{:.1}%", selected_file.size.savings()),
+                false => String::from("–"),
+            });
+        }
+        Column::Dimensions => {
+            ui.label(dimensions(selected_file.original_dimensions));
+        }
+        Column::NewDimensions => {
+            ui.label(dimensions(selected_file.dimensions));
+        }
+        Column::Duration => {
+            ui.label(match selected_file.duration {
+                Some(duration) => format!("{:.1} s", duration.as_secs_f32()),
+                None => String::from("–"),
+            });
+        }
+        Column::Status => render_status(ui, selected_file, is_running),
+    }
+}
+
+fn render_status(ui: &mut Ui, selected_file: &SelectedFile, is_running: bool) {
+    match selected_file.status {
+        FileStatus::Done => {
+            match selected_file.larger_output {
+                Some(LargerOutputPolicy::CopyOriginal) => ui
+                    .label("Kept original ↩")
+                    .on_hover_text("The shrinked file was larger, the original was copied"),
+                Some(LargerOutputPolicy::Skip) => ui
+                    .label("Skipped ⏭")
+                    .on_hover_text("The shrinked file was larger and not written"),
+                Some(LargerOutputPolicy::Retry) => ui
+                    .label("Done ✅")
+                    .on_hover_text("Retried with a lower quality, the first result was larger"),
+                None => ui.label("Done ✅"),
+            };
+        }
+        FileStatus::UpToDate => {
+            ui.label("Up to date ✔")
+                .on_hover_text("Didn't change since it was shrinked with these settings");
+        }
+        FileStatus::Failed => {
+            let failed = ui.colored_label(Color32::RED, "Failed ⚠");
+            if let Some(err) = &selected_file.error {
+                failed.on_hover_text(err.to_string());
             }
-            // Add label if file has been compressed
-            if done || (has_run_once && !is_running) {
-                ui.label("Done ✅");
-                ui.add_space(5.);
-            } else if is_running {
-                Spinner::default().ui(ui);
+        }
+        FileStatus::Cancelled => {
+            ui.label("Cancelled ⏹");
+        }
+        FileStatus::Exists => {
+            ui.label("Exists ⏭")
+                .on_hover_text("The output file already existed and was left alone");
+        }
+        FileStatus::Pending if is_running => {
+            match (selected_file.started, selected_file.progress) {
+                (_, Some(fraction)) => {
+                    ProgressBar::new(fraction)
+                        .desired_width(60.)
+                        .show_percentage()
+                        .ui(ui);
+                }
+                (true, None) => {
+                    Spinner::default().ui(ui);
+                }
+                (false, None) => {
+                    ui.label(RichText::new("Queued").weak());
+                }
             }
-            ui.label(format!(
-                "{}%",
-                round_percent(
-                    selected_file.size.new.load(Ordering::Relaxed),
-                    selected_file.size.original,
-                )
-            ));
-        });
-    });
-    ui.separator();
-    (done, remove_file)
+        }
+        FileStatus::Pending => {
+            ui.label("");
+        }
+    }
+}
+
+/// Quality, similarity and responsive sizes of a shrinked file
+fn render_details(ui: &mut Ui, selected_file: &SelectedFile) {
+    if let Some(quality) = selected_file.quality {
+        ui.label(RichText::new(format!("q{quality}")).weak())
+            .on_hover_text("Quality");
+    }
+    if let Some(similarity) = selected_file.similarity {
+        ui.label(RichText::new(format!("SSIM {:.3}", similarity.ssim)).weak())
+            .on_hover_text(format!("PSNR {:.1} dB", similarity.psnr));
+    }
+    if !selected_file.variants.is_empty() {
+        ui.label(RichText::new(format!("{} sizes", selected_file.variants.len())).weak())
+            .on_hover_text(
+                selected_file
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        format!(
+                            "{}x{}: {} Kb",
+                            variant.width,
+                            variant.height,
+                            variant.size / 1024
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+    }
+}
+
+fn load_texture(ctx: &Context, name: &str, pixels: &Pixels) -> TextureHandle {
+    ctx.load_texture(
+        name,
+        ColorImage::from_rgba_unmultiplied([pixels.width, pixels.height], &pixels.rgba),
+    )
 }
 
 pub fn render_header(ui: &mut Ui) {
@@ -589,9 +1960,11 @@ pub fn render_header(ui: &mut Ui) {
 pub fn render_footer(
     ctx: &Context,
     total_file_size: u64,
-    total_new_file_size: Arc<AtomicU64>,
+    total_new_file_size: u64,
     has_run_once: bool,
     file_count: usize,
+    kept_count: usize,
+    up_to_date_count: usize,
 ) {
     TopBottomPanel::bottom("footer").show(ctx, |ui| {
         ui.vertical_centered(|ui| {
@@ -605,9 +1978,17 @@ pub fn render_footer(
             ui.add_enabled(
                 has_run_once,
                 Label::new(format!(
-                    "☞ New size: {} Kb ({}%)",
-                    total_new_file_size.load(Ordering::Relaxed) / 1024,
-                    round_percent(total_new_file_size.load(Ordering::Relaxed), total_file_size)
+                    "☞ New size: {} Kb ({}%){}{}",
+                    total_new_file_size / 1024,
+                    round_percent(total_new_file_size, total_file_size),
+                    match kept_count {
+                        0 => String::new(),
+                        _ => format!(", {kept_count} originals kept"),
+                    },
+                    match up_to_date_count {
+                        0 => String::new(),
+                        _ => format!(", {up_to_date_count} up to date"),
+                    }
                 )),
             );
             ui.add_space(PADDING);
@@ -0,0 +1,181 @@
+//! Just enough ICC to describe sRGB, images are converted to it before their own profile is
+//! stripped
+
+/// sRGB primaries adapted to the D50 white of the profile connection space
+const RED: [f64; 3] = [0.436_074_7, 0.222_504_5, 0.013_932_2];
+const GREEN: [f64; 3] = [0.385_064_9, 0.716_878_6, 0.097_104_5];
+const BLUE: [f64; 3] = [0.143_080_4, 0.060_616_9, 0.714_173_3];
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+/// Bradford adaptation from the D65 white of sRGB to D50, row by row
+const CHROMATIC_ADAPTATION: [f64; 9] = [
+    1.047_811_2,
+    0.022_886_6,
+    -0.050_127_0,
+    0.029_542_4,
+    0.990_484_4,
+    -0.017_049_1,
+    -0.009_234_5,
+    0.015_043_6,
+    0.752_131_6,
+];
+/// sRGB transfer function as parametric curve 3: gamma, a, b, c and d of
+/// `(a * x + b) ^ gamma` if `x >= d`, `c * x` otherwise
+const TRANSFER: [f64; 5] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+const HEADER_SIZE: usize = 128;
+/// Signature, offset and size
+const TAG_ENTRY_SIZE: usize = 12;
+
+/// ICC v4 display profile with the primaries, white point and transfer curve of sRGB
+pub fn srgb_profile() -> Vec<u8> {
+    let tags = [
+        (b"desc", multi_localized("sRGB")),
+        (b"cprt", multi_localized("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"chad", s15_fixed16_array(&CHROMATIC_ADAPTATION)),
+        (b"rXYZ", xyz(RED)),
+        (b"gXYZ", xyz(GREEN)),
+        (b"bXYZ", xyz(BLUE)),
+        (b"rTRC", parametric_curve()),
+        (b"gTRC", parametric_curve()),
+        (b"bTRC", parametric_curve()),
+    ];
+
+    // The tag table follows the header, the tags follow the table aligned to 4 bytes
+    let data_start = HEADER_SIZE + 4 + tags.len() * TAG_ENTRY_SIZE;
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let mut profile = Vec::with_capacity(data_start + data.len());
+    profile.extend(((data_start + data.len()) as u32).to_be_bytes());
+    // No preferred CMM
+    profile.extend([0; 4]);
+    // Version 4.3
+    profile.extend([4, 0x30, 0, 0]);
+    profile.extend(b"mntrRGB XYZ ");
+    // Creation date 2022-05-01 00:00:00
+    for value in [2022u16, 5, 1, 0, 0, 0] {
+        profile.extend(value.to_be_bytes());
+    }
+    profile.extend(b"acsp");
+    // Platform, flags, manufacturer, model, attributes and perceptual rendering intent
+    profile.resize(68, 0);
+    for value in D50 {
+        profile.extend(s15_fixed16(value));
+    }
+    // Creator, the ID of an unchecked profile and reserved bytes
+    profile.resize(HEADER_SIZE, 0);
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+/// Tag type signature followed by 4 reserved bytes
+fn tag(signature: &[u8; 4]) -> Vec<u8> {
+    let mut tag = signature.to_vec();
+    tag.extend([0; 4]);
+    tag
+}
+
+fn xyz(xyz: [f64; 3]) -> Vec<u8> {
+    let mut tag = tag(b"XYZ ");
+    for value in xyz {
+        tag.extend(s15_fixed16(value));
+    }
+    tag
+}
+
+fn s15_fixed16_array(values: &[f64]) -> Vec<u8> {
+    let mut tag = tag(b"sf32");
+    for value in values {
+        tag.extend(s15_fixed16(*value));
+    }
+    tag
+}
+
+fn parametric_curve() -> Vec<u8> {
+    let mut tag = tag(b"para");
+    // Function type 3 and 2 reserved bytes
+    tag.extend([0, 3, 0, 0]);
+    for value in TRANSFER {
+        tag.extend(s15_fixed16(value));
+    }
+    tag
+}
+
+/// A single English `text`, encoded as UTF-16
+fn multi_localized(text: &str) -> Vec<u8> {
+    let text = text
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect::<Vec<_>>();
+    let mut tag = tag(b"mluc");
+    // One record of 12 bytes, its text starts right after it
+    tag.extend(1u32.to_be_bytes());
+    tag.extend(12u32.to_be_bytes());
+    tag.extend(b"enUS");
+    tag.extend((text.len() as u32).to_be_bytes());
+    tag.extend(28u32.to_be_bytes());
+    tag.extend(text);
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(profile: &[u8], at: usize) -> usize {
+        u32::from_be_bytes(profile[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn header_describes_the_profile() {
+        let profile = srgb_profile();
+        assert_eq!(u32_at(&profile, 0), profile.len());
+        assert_eq!(profile.len() % 4, 0);
+        assert_eq!(&profile[12..24], b"mntrRGB XYZ ");
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(profile[68..80], D50.map(s15_fixed16).concat());
+    }
+
+    #[test]
+    fn tags_are_aligned_and_inside_the_profile() {
+        let profile = srgb_profile();
+        let count = u32_at(&profile, HEADER_SIZE);
+        assert_eq!(count, 10);
+        let data_start = HEADER_SIZE + 4 + count * TAG_ENTRY_SIZE;
+        let mut previous_end = data_start;
+        for i in 0..count {
+            let entry = HEADER_SIZE + 4 + i * TAG_ENTRY_SIZE;
+            let (offset, size) = (u32_at(&profile, entry + 4), u32_at(&profile, entry + 8));
+            assert_eq!(offset % 4, 0);
+            // Tags don't overlap and only padding is between them
+            assert!(offset >= previous_end && offset - previous_end < 4);
+            assert!(offset + size <= profile.len());
+            previous_end = offset + size;
+        }
+        assert_eq!(previous_end.next_multiple_of(4), profile.len());
+    }
+
+    #[test]
+    fn transfer_curves_are_parametric() {
+        let profile = srgb_profile();
+        let red_trc = HEADER_SIZE + 4 + 7 * TAG_ENTRY_SIZE;
+        assert_eq!(&profile[red_trc..red_trc + 4], b"rTRC");
+        let offset = u32_at(&profile, red_trc + 4);
+        assert_eq!(&profile[offset..offset + 4], b"para");
+        assert_eq!(u32_at(&profile, red_trc + 8), 12 + TRANSFER.len() * 4);
+        // Gamma 2.4 as s15Fixed16
+        assert_eq!(u32_at(&profile, offset + 12), 157_286);
+    }
+}
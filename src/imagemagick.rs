@@ -1,33 +1,941 @@
-use std::sync::Arc;
+use std::{
+    ffi::{c_void, CString},
+    fs,
+    path::Path,
+    sync::Once,
+};
 
-use magick_rust::{MagickError, MagickWand};
+use magick_rust::{bindings, magick_wand_genesis, MagickError, MagickWand, PixelWand};
+use serde::{Deserialize, Serialize};
 
-use crate::utils::Dimensions;
+use crate::{
+    error::{Result, RshrinkError},
+    exif, icc,
+    utils::{Dimensions, ResizeMode},
+};
 
-pub fn perform_magick(
+static START: Once = Once::new();
+const MAX_QUALITY: usize = 100;
+/// How often the dimensions are reduced in target size mode before giving up
+const MAX_RESIZE_STEPS: usize = 10;
+/// Each step keeps this percentage of the previous width and height
+const RESIZE_STEP_PERCENT: usize = 80;
+/// Peak signal to noise ratio of identical images is infinite
+const MAX_PSNR: f64 = 100.0;
+
+/// Initialize imagemagick, has to be called before any wand is created
+pub fn init() {
+    START.call_once(|| {
+        magick_wand_genesis();
+    });
+}
+
+/// Decoded image as unmultiplied 8 bit RGBA pixels
+pub struct Pixels {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+/// Image format the shrinked files are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    /// Keep the format of the input file
+    #[default]
+    Original,
+    Jpeg,
+    Png,
+    WebP,
+    Avif,
+    Jxl,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 6] = [
+        OutputFormat::Original,
+        OutputFormat::Jpeg,
+        OutputFormat::Png,
+        OutputFormat::WebP,
+        OutputFormat::Avif,
+        OutputFormat::Jxl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Original => "Original",
+            OutputFormat::Jpeg => "JPEG",
+            OutputFormat::Png => "PNG",
+            OutputFormat::WebP => "WebP",
+            OutputFormat::Avif => "AVIF",
+            OutputFormat::Jxl => "JPEG XL",
+        }
+    }
+
+    /// File extension of the output, `None` keeps the input's extension
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            OutputFormat::Original => None,
+            OutputFormat::Jpeg => Some("jpg"),
+            OutputFormat::Png => Some("png"),
+            OutputFormat::WebP => Some("webp"),
+            OutputFormat::Avif => Some("avif"),
+            OutputFormat::Jxl => Some("jxl"),
+        }
+    }
+
+    /// Transparent areas have to be flattened for formats without an alpha channel
+    pub fn supports_alpha(&self) -> bool {
+        !matches!(self, OutputFormat::Jpeg)
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "original" => Ok(OutputFormat::Original),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "png" => Ok(OutputFormat::Png),
+            "webp" => Ok(OutputFormat::WebP),
+            "avif" => Ok(OutputFormat::Avif),
+            "jxl" => Ok(OutputFormat::Jxl),
+            _ => Err(format!(
+                "Unknown format {format}, expected original, jpeg, png, webp, avif or jxl"
+            )),
+        }
+    }
+}
+
+/// How PNGs are compressed, they're never encoded like JPEGs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PngMode {
+    /// Lossless if the image has at most `png_colors` colors, quantized otherwise
+    Auto,
+    /// Only recompress, the pixels stay exactly the same
+    #[default]
+    Lossless,
+    /// Always reduce the colors to a palette of `png_colors`
+    Quantize,
+}
+
+impl PngMode {
+    pub const ALL: [PngMode; 3] = [PngMode::Lossless, PngMode::Auto, PngMode::Quantize];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngMode::Auto => "Auto",
+            PngMode::Lossless => "Lossless",
+            PngMode::Quantize => "Reduce colors",
+        }
+    }
+}
+
+impl std::str::FromStr for PngMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> std::result::Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "auto" => Ok(PngMode::Auto),
+            "lossless" => Ok(PngMode::Lossless),
+            "quantize" => Ok(PngMode::Quantize),
+            _ => Err(format!(
+                "Unknown PNG mode {mode}, expected auto, lossless or quantize"
+            )),
+        }
+    }
+}
+
+/// Chroma subsampling of JPEGs, how much color resolution is traded for size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Subsampling {
+    /// Full color resolution, for text and sharp colored edges
+    S444,
+    /// Half horizontal color resolution
+    S422,
+    /// Half horizontal and vertical color resolution, smallest
+    #[default]
+    S420,
+}
+
+impl Subsampling {
+    pub const ALL: [Subsampling; 3] = [Subsampling::S444, Subsampling::S422, Subsampling::S420];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Subsampling::S444 => "4:4:4",
+            Subsampling::S422 => "4:2:2",
+            Subsampling::S420 => "4:2:0",
+        }
+    }
+
+    /// Horizontal and vertical sampling factor of the luma channel
+    fn factors(&self) -> [f64; 2] {
+        match self {
+            Subsampling::S444 => [1.0, 1.0],
+            Subsampling::S422 => [2.0, 1.0],
+            Subsampling::S420 => [2.0, 2.0],
+        }
+    }
+}
+
+impl std::str::FromStr for Subsampling {
+    type Err = String;
+
+    fn from_str(subsampling: &str) -> std::result::Result<Self, Self::Err> {
+        match subsampling.replace(':', "").as_str() {
+            "444" => Ok(Subsampling::S444),
+            "422" => Ok(Subsampling::S422),
+            "420" => Ok(Subsampling::S420),
+            _ => Err(format!(
+                "Unknown subsampling {subsampling}, expected 444, 422 or 420"
+            )),
+        }
+    }
+}
+
+/// Colorspace JPEGs are converted to before encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JpegColorspace {
+    #[default]
+    Keep,
+    /// E.g. for CMYK print files that end up on the web
+    Srgb,
+    Gray,
+}
+
+impl JpegColorspace {
+    pub const ALL: [JpegColorspace; 3] = [
+        JpegColorspace::Keep,
+        JpegColorspace::Srgb,
+        JpegColorspace::Gray,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JpegColorspace::Keep => "Keep",
+            JpegColorspace::Srgb => "sRGB",
+            JpegColorspace::Gray => "Grayscale",
+        }
+    }
+}
+
+impl std::str::FromStr for JpegColorspace {
+    type Err = String;
+
+    fn from_str(colorspace: &str) -> std::result::Result<Self, Self::Err> {
+        match colorspace.to_lowercase().as_str() {
+            "keep" => Ok(JpegColorspace::Keep),
+            "srgb" => Ok(JpegColorspace::Srgb),
+            "gray" => Ok(JpegColorspace::Gray),
+            _ => Err(format!(
+                "Unknown colorspace {colorspace}, expected keep, srgb or gray"
+            )),
+        }
+    }
+}
+
+/// Output format and its format specific encoder settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatOptions {
+    pub format: OutputFormat,
+    /// RGB color transparent areas are flattened against for formats without alpha
+    pub background: [u8; 3],
+    pub webp_lossless: bool,
+    /// AVIF encoder speed from 0 (smallest) to 10 (fastest)
+    pub avif_speed: u8,
+    /// JPEG XL encoder effort from 1 (fastest) to 9 (smallest)
+    pub jxl_effort: u8,
+    pub png_mode: PngMode,
+    /// Palette size of quantized PNGs, from 2 to 256
+    pub png_colors: usize,
+    /// Dither quantized PNGs, smoother gradients but larger files
+    pub png_dither: bool,
+    /// zlib level from 0 (fastest) to 9 (smallest)
+    pub png_compression_level: u8,
+    /// Store 16 bit PNGs with 8 bits per channel when they're quantized
+    pub png_reduce_depth: bool,
+    pub jpeg_subsampling: Subsampling,
+    /// Progressive JPEGs show up blurry first while loading, baseline ones line by line
+    pub jpeg_progressive: bool,
+    /// Compute optimal Huffman tables instead of the standard ones
+    pub jpeg_optimize_coding: bool,
+    /// Smaller than Huffman coding, but needs a libjpeg with arithmetic coding and isn't
+    /// supported by most browsers
+    pub jpeg_arithmetic_coding: bool,
+    pub jpeg_colorspace: JpegColorspace,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Original,
+            background: [255, 255, 255],
+            webp_lossless: false,
+            avif_speed: 6,
+            jxl_effort: 7,
+            png_mode: PngMode::default(),
+            png_colors: 256,
+            png_dither: true,
+            png_compression_level: 9,
+            png_reduce_depth: true,
+            jpeg_subsampling: Subsampling::default(),
+            jpeg_progressive: true,
+            jpeg_optimize_coding: true,
+            jpeg_arithmetic_coding: false,
+            jpeg_colorspace: JpegColorspace::default(),
+        }
+    }
+}
+
+/// Which metadata survives shrinking, everything else is stripped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataOptions {
+    /// Rotate images according to their EXIF orientation before it's stripped
+    pub auto_orient: bool,
+    /// Only remove GPS coordinates and keep all other metadata
+    pub strip_gps_only: bool,
+    pub keep_icc: bool,
+    /// Keep the EXIF artist and copyright
+    pub keep_copyright: bool,
+    /// Convert images to sRGB if their ICC profile is stripped, it's kept if that fails
+    pub convert_to_srgb: bool,
+}
+
+impl Default for MetadataOptions {
+    fn default() -> Self {
+        Self {
+            auto_orient: true,
+            strip_gps_only: false,
+            keep_icc: true,
+            keep_copyright: false,
+            convert_to_srgb: true,
+        }
+    }
+}
+
+/// How images are shrinked, shared by all imagemagick operations of a batch
+#[derive(Debug, Clone)]
+pub struct MagickOptions {
+    /// Fit images into these dimensions, keep the original size if `None`
+    pub dims: Option<Dimensions>,
+    pub compression_quality: usize,
+    pub apply_gaussian_blur: bool,
+    pub format: FormatOptions,
+    pub metadata: MetadataOptions,
+    /// Compare every result with the original
+    pub metrics: bool,
+    /// Search the lowest quality whose SSIM reaches this instead of using `compression_quality`
+    pub min_ssim: Option<f64>,
+}
+
+/// Shrink `in_file` with `compression_quality`, or the lowest quality reaching `min_ssim`
+pub fn perform_magick(in_file: &str, out_file: &str, options: &MagickOptions) -> Result<Written> {
+    let format = blob_format(in_file, &options.format);
+    let (mut wand, image_colors) = prepare_wand(in_file, options, format)?;
+    finish_wand(&mut wand, options)?;
+    let reference = reference(&wand, options);
+    encode_wand(
+        &mut wand,
+        options,
+        format,
+        options.compression_quality,
+        image_colors,
+    )?;
+    write_encoded(&mut wand, reference.as_ref(), format, options, out_file)
+}
+
+/// Shrink `in_file` to at most `max_size` bytes, trying qualities in memory and writing only the
+/// final result. If even the lowest quality is too big and `allow_resize` is set, the dimensions
+/// are reduced step by step
+pub fn perform_magick_target_size(
     in_file: &str,
     out_file: &str,
-    dims: Arc<Option<Dimensions>>,
-    compression_quality: usize,
-    apply_gaussian_blur: bool,
-) -> Result<(), MagickError> {
+    options: &MagickOptions,
+    max_size: u64,
+    allow_resize: bool,
+) -> Result<Written> {
+    let format = blob_format(in_file, &options.format);
+    let (source, image_colors) = read_wand(in_file, options, format)?;
+    let mut options = options.clone();
+    let mut smallest = u64::MAX;
+    for _ in 0..=MAX_RESIZE_STEPS {
+        let mut wand = source.clone();
+        if let Some(dims) = &options.dims {
+            resize_wand(&mut wand, dims)?;
+        }
+        finish_wand(&mut wand, &options)?;
+        let reference = reference(&wand, &options);
+        encode_wand(&mut wand, &options, format, MAX_QUALITY, image_colors)?;
+        let (found, smallest_size) = search_quality(&mut wand, format, max_size)?;
+        if let Some((quality, blob)) = found {
+            return write_blob(&wand, reference.as_ref(), out_file, quality, blob);
+        }
+        smallest = smallest.min(smallest_size);
+
+        let (width, height) = (wand.get_image_width(), wand.get_image_height());
+        if !allow_resize || width <= 1 || height <= 1 {
+            break;
+        }
+        options.dims = Some(Dimensions::new(
+            (width * RESIZE_STEP_PERCENT / 100).max(1),
+            (height * RESIZE_STEP_PERCENT / 100).max(1),
+        ));
+    }
+
+    Err(RshrinkError::TargetSizeUnreachable {
+        target: max_size,
+        smallest,
+    })
+}
+
+/// How close a shrinked image is to the original scaled to the same size
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Similarity {
+    /// Structural similarity from 0 to 1 (identical)
+    pub ssim: f64,
+    /// Peak signal to noise ratio in dB, capped at `MAX_PSNR` for identical images
+    pub psnr: f64,
+}
+
+/// Size, quality and similarity of a written image
+#[derive(Debug, Clone, Copy)]
+pub struct Written {
+    pub width: usize,
+    pub height: usize,
+    pub quality: usize,
+    /// Only compared with `MagickOptions::metrics` or `min_ssim`
+    pub similarity: Option<Similarity>,
+}
+
+/// Read `in_file` once and write a copy for every dimensions of `variants`, `options.dims` is
+/// ignored. With `max_size` the quality is searched per variant, the dimensions stay as they are.
+/// A variant that comes out as wide as an earlier one, e.g. a width larger than the image in
+/// `ResizeMode::ShrinkOnly`, isn't written and `None`
+pub fn perform_magick_variants(
+    in_file: &str,
+    variants: &[(&Dimensions, &str)],
+    options: &MagickOptions,
+    max_size: Option<u64>,
+) -> Result<Vec<Option<Written>>> {
+    let format = blob_format(in_file, &options.format);
+    let (wand, image_colors) = read_wand(in_file, options, format)?;
+
+    let mut written = Vec::new();
+    let mut widths = Vec::new();
+    for (dims, out_file) in variants {
+        let mut variant = wand.clone();
+        resize_wand(&mut variant, dims)?;
+        if widths.contains(&variant.get_image_width()) {
+            written.push(None);
+            continue;
+        }
+        widths.push(variant.get_image_width());
+        finish_wand(&mut variant, options)?;
+        let reference = reference(&variant, options);
+        encode_wand(
+            &mut variant,
+            options,
+            format,
+            options.compression_quality,
+            image_colors,
+        )?;
+        written.push(Some(match max_size {
+            Some(max_size) => match search_quality(&mut variant, format, max_size)? {
+                (Some((quality, blob)), _) => {
+                    write_blob(&variant, reference.as_ref(), out_file, quality, blob)?
+                }
+                (None, smallest) => {
+                    return Err(RshrinkError::TargetSizeUnreachable {
+                        target: max_size,
+                        smallest,
+                    })
+                }
+            },
+            None => write_encoded(&mut variant, reference.as_ref(), format, options, out_file)?,
+        }));
+    }
+    Ok(written)
+}
+
+/// Unencoded copy of a finished wand to compare the result with, if metrics are needed
+fn reference(wand: &MagickWand, options: &MagickOptions) -> Option<MagickWand> {
+    match options.metrics || options.min_ssim.is_some() {
+        true => Some(wand.clone()),
+        false => None,
+    }
+}
+
+/// Write `wand` with `compression_quality`, or the lowest quality reaching `min_ssim`
+fn write_encoded(
+    wand: &mut MagickWand,
+    reference: Option<&MagickWand>,
+    format: &str,
+    options: &MagickOptions,
+    out_file: &str,
+) -> Result<Written> {
+    match (options.min_ssim, reference) {
+        (Some(min_ssim), Some(reference)) => {
+            let (quality, blob) = search_min_ssim(wand, reference, format, min_ssim)?;
+            write_blob(wand, Some(reference), out_file, quality, blob)
+        }
+        (_, Some(reference)) => {
+            let blob = wand.write_image_blob(format)?;
+            write_blob(
+                wand,
+                Some(reference),
+                out_file,
+                options.compression_quality,
+                blob,
+            )
+        }
+        (_, None) => {
+            wand.write_image(out_file)?;
+            Ok(Written {
+                width: wand.get_image_width(),
+                height: wand.get_image_height(),
+                quality: options.compression_quality,
+                similarity: None,
+            })
+        }
+    }
+}
+
+/// Write the encoded `blob` of `wand` and compare it with `reference`
+fn write_blob(
+    wand: &MagickWand,
+    reference: Option<&MagickWand>,
+    out_file: &str,
+    quality: usize,
+    blob: Vec<u8>,
+) -> Result<Written> {
+    let similarity = match reference {
+        Some(reference) => Some(similarity(reference, &blob)?),
+        None => None,
+    };
+    fs::write(out_file, blob).map_err(|err| RshrinkError::io(out_file, err))?;
+    Ok(Written {
+        width: wand.get_image_width(),
+        height: wand.get_image_height(),
+        quality,
+        similarity,
+    })
+}
+
+/// Decode `blob` and compare it with `reference`, which has to have the same dimensions
+fn similarity(reference: &MagickWand, blob: &[u8]) -> Result<Similarity> {
+    let decoded = MagickWand::new();
+    decoded.read_image_blob(blob)?;
+    let distortion = |metric| {
+        let mut distortion = 0.0;
+        let result = unsafe {
+            bindings::MagickGetImageDistortion(
+                decoded.wand,
+                reference.wand,
+                metric,
+                &mut distortion,
+            )
+        };
+        match result {
+            bindings::MagickBooleanType_MagickTrue => Ok(distortion),
+            _ => Err(MagickError(
+                "failed to compare the result with the original",
+            )),
+        }
+    };
+    // MetricType (MagickCore/compare.h): 8 = PeakSignalToNoiseRatioErrorMetric,
+    // 11 = StructuralSimilarityErrorMetric
+    let psnr = distortion(8)?;
+    Ok(Similarity {
+        ssim: distortion(11)?,
+        psnr: match psnr.is_finite() {
+            true => psnr.min(MAX_PSNR),
+            false => MAX_PSNR,
+        },
+    })
+}
+
+/// Binary search the lowest quality whose SSIM compared to `reference` is at least `min_ssim`,
+/// the highest quality if none reaches it
+fn search_min_ssim(
+    wand: &mut MagickWand,
+    reference: &MagickWand,
+    format: &str,
+    min_ssim: f64,
+) -> Result<(usize, Vec<u8>)> {
+    let (mut low, mut high) = (1, MAX_QUALITY);
+    let mut found = None;
+    while low <= high {
+        let quality = (low + high) / 2;
+        wand.set_image_compression_quality(quality)?;
+        let blob = wand.write_image_blob(format)?;
+        if similarity(reference, &blob)?.ssim >= min_ssim {
+            found = Some((quality, blob));
+            high = quality - 1;
+        } else {
+            low = quality + 1;
+        }
+    }
+    match found {
+        Some(found) => Ok(found),
+        None => {
+            wand.set_image_compression_quality(MAX_QUALITY)?;
+            Ok((MAX_QUALITY, wand.write_image_blob(format)?))
+        }
+    }
+}
+
+/// Binary search the highest quality whose encoded size fits into `max_size`, also returns the
+/// smallest size seen
+fn search_quality(
+    wand: &mut MagickWand,
+    format: &str,
+    max_size: u64,
+) -> Result<(Option<(usize, Vec<u8>)>, u64)> {
+    let (mut low, mut high) = (1, MAX_QUALITY);
+    let mut found = None;
+    let mut smallest = u64::MAX;
+    while low <= high {
+        let quality = (low + high) / 2;
+        wand.set_image_compression_quality(quality)?;
+        let blob = wand.write_image_blob(format)?;
+        let size = blob.len() as u64;
+        smallest = smallest.min(size);
+        if size <= max_size {
+            found = Some((quality, blob));
+            low = quality + 1;
+        } else {
+            high = quality - 1;
+        }
+    }
+    Ok((found, smallest))
+}
+
+/// Shrink `in_file` like `perform_magick`, but return the encoded file instead of writing it
+pub fn render_magick(in_file: &str, options: &MagickOptions) -> Result<Vec<u8>> {
+    let format = blob_format(in_file, &options.format);
+    let (mut wand, image_colors) = prepare_wand(in_file, options, format)?;
+    finish_wand(&mut wand, options)?;
+    encode_wand(
+        &mut wand,
+        options,
+        format,
+        options.compression_quality,
+        image_colors,
+    )?;
+    Ok(wand.write_image_blob(format)?)
+}
+
+/// Format name for in-memory encoding, perform_magick picks it through the file extension
+fn blob_format<'a>(in_file: &'a str, format: &FormatOptions) -> &'a str {
+    match format.format.extension() {
+        Some(extension) => extension,
+        None => Path::new(in_file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("jpg"),
+    }
+}
+
+/// Width and height of `in_file` as it's shown, only its header is read
+pub fn image_dimensions(in_file: &str) -> Option<(usize, usize)> {
+    let wand = MagickWand::new();
+    wand.ping_image(in_file).ok()?;
+    let orientation = wand.get_image_orientation();
+    let (width, height) = (wand.get_image_width(), wand.get_image_height());
+    // OrientationType (MagickCore/image.h): 5 to 8 are rotated by 90 degrees
+    match (width, orientation) {
+        (0, _) => None,
+        (_, 5..=8) => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
+
+/// Decode an encoded image, e.g. from `render_magick` or a file read into memory
+pub fn decode_pixels(blob: &[u8]) -> Result<Pixels> {
+    let wand = MagickWand::new();
+    wand.read_image_blob(blob)?;
+    let (width, height) = (wand.get_image_width(), wand.get_image_height());
+    let rgba = wand
+        .export_image_pixels(0, 0, width, height, "RGBA")
+        .ok_or(RshrinkError::ExportPixels)?;
+    Ok(Pixels {
+        width,
+        height,
+        rgba,
+    })
+}
+
+/// Read `in_file`, apply the metadata options and resize it, ready to be encoded. Also returns
+/// the colors `read_wand` counted
+fn prepare_wand(
+    in_file: &str,
+    options: &MagickOptions,
+    format: &str,
+) -> Result<(MagickWand, Option<usize>)> {
+    let (mut wand, image_colors) = read_wand(in_file, options, format)?;
+    if let Some(dims) = &options.dims {
+        resize_wand(&mut wand, dims)?;
+    }
+    Ok((wand, image_colors))
+}
+
+/// Read `in_file` and apply the metadata options. The colors are counted once if they decide
+/// whether a PNG is quantized, before resizing adds new ones
+fn read_wand(
+    in_file: &str,
+    options: &MagickOptions,
+    format: &str,
+) -> Result<(MagickWand, Option<usize>)> {
     let mut wand = MagickWand::new();
     wand.read_image(in_file)?;
-    if let Some(dims) = dims.as_ref() {
-        // TODO: Check if provided dimensions are actually smaller than original dimensions
-        wand.fit(dims.width, dims.height);
+    // Orientation has to be applied before the dimensions are compared
+    apply_metadata(&mut wand, &options.metadata)?;
+    let image_colors = match (is_png(format), options.format.png_mode) {
+        (true, PngMode::Auto) => Some(wand.get_image_colors()),
+        _ => None,
+    };
+    Ok((wand, image_colors))
+}
+
+fn is_png(format: &str) -> bool {
+    format.eq_ignore_ascii_case("png")
+}
+
+/// Blur and output format of an image that is already resized, the pixels the encoder gets
+fn finish_wand(wand: &mut MagickWand, options: &MagickOptions) -> Result<()> {
+    if options.apply_gaussian_blur {
+        // Pretty slow
+        wand.gaussian_blur_image(0.05, 1.0)?
+    }
+    apply_format(wand, &options.format)
+}
+
+/// Encoder settings of a finished image, `format` is the output's format name
+fn encode_wand(
+    wand: &mut MagickWand,
+    options: &MagickOptions,
+    format: &str,
+    compression_quality: usize,
+    image_colors: Option<usize>,
+) -> Result<()> {
+    match format.to_lowercase().as_str() {
+        "png" => encode_png(wand, &options.format, image_colors),
+        "jpg" | "jpeg" => encode_jpeg(wand, &options.format, compression_quality),
+        _ => Ok(wand.set_image_compression_quality(compression_quality)?),
+    }
+}
+
+/// Strip metadata according to `metadata`
+fn apply_metadata(wand: &mut MagickWand, metadata: &MetadataOptions) -> Result<()> {
+    if metadata.auto_orient {
+        // Also resets the orientation tag, so viewers don't rotate the image a second time
+        if !wand.auto_orient() {
+            return Err(MagickError("failed to orient image").into());
+        }
+    }
+    if metadata.strip_gps_only {
+        if let Some(mut profile) = image_profile(wand, "exif") {
+            exif::remove_gps(&mut profile);
+            set_image_profile(wand, "exif", &profile);
+        }
+        // XMP can contain the location as well
+        remove_image_profile(wand, "xmp");
+        return Ok(());
     }
-    wand.set_sampling_factors(&[4.0, 2.0, 0.0])?;
+
+    let icc = image_profile(wand, "icc");
+    // Without a profile viewers assume sRGB, so the pixels are converted through the profiles.
+    // If that isn't possible, e.g. without LittleCMS, the profile is kept to not shift colors.
+    let keep_icc = metadata.keep_icc
+        || (icc.is_some()
+            && metadata.convert_to_srgb
+            && wand
+                .profile_image("icc", Some(icc::srgb_profile().as_slice()))
+                .is_err());
+    let copyright = match metadata.keep_copyright {
+        true => exif::copyright_profile(
+            wand.get_image_property("exif:Artist").ok().as_deref(),
+            wand.get_image_property("exif:Copyright").ok().as_deref(),
+        ),
+        false => None,
+    };
     wand.strip_image()?;
+    if let (Some(icc), true) = (icc, keep_icc) {
+        set_image_profile(wand, "icc", &icc);
+    }
+    if let Some(copyright) = copyright {
+        set_image_profile(wand, "exif", &copyright);
+    }
+    Ok(())
+}
+
+/// Copy of the profile `name`, e.g. icc or exif
+fn image_profile(wand: &MagickWand, name: &str) -> Option<Vec<u8>> {
+    let name = CString::new(name).ok()?;
+    let mut length = 0;
+    unsafe {
+        let profile = bindings::MagickGetImageProfile(wand.wand, name.as_ptr(), &mut length);
+        if profile.is_null() {
+            return None;
+        }
+        let copy = std::slice::from_raw_parts(profile, length).to_vec();
+        bindings::MagickRelinquishMemory(profile as *mut c_void);
+        Some(copy)
+    }
+}
+
+fn set_image_profile(wand: &mut MagickWand, name: &str, profile: &[u8]) {
+    if let Ok(name) = CString::new(name) {
+        // Only fails if imagemagick runs out of memory
+        unsafe {
+            bindings::MagickSetImageProfile(
+                wand.wand,
+                name.as_ptr(),
+                profile.as_ptr() as *const c_void,
+                profile.len(),
+            );
+        }
+    }
+}
+
+fn remove_image_profile(wand: &mut MagickWand, name: &str) {
+    if let Ok(name) = CString::new(name) {
+        let mut length = 0;
+        unsafe {
+            let profile = bindings::MagickRemoveImageProfile(wand.wand, name.as_ptr(), &mut length);
+            if !profile.is_null() {
+                bindings::MagickRelinquishMemory(profile as *mut c_void);
+            }
+        }
+    }
+}
+
+/// Scale the image according to the resize mode of `dims`
+fn resize_wand(wand: &mut MagickWand, dims: &Dimensions) -> Result<()> {
+    let (width, height) = (wand.get_image_width(), wand.get_image_height());
+    let (new_width, new_height) = dims.scaled_size(width, height);
+    if (new_width, new_height) != (width, height) {
+        // 22 = LanczosFilter
+        wand.resize_image(new_width, new_height, 22);
+    }
+    if dims.mode == ResizeMode::Fill {
+        let crop_width = dims.width.clamp(1, new_width);
+        let crop_height = dims.height.clamp(1, new_height);
+        let x = ((new_width - crop_width) / 2) as isize;
+        let y = ((new_height - crop_height) / 2) as isize;
+        // The page is reset so no offset is written
+        wand.crop_image(crop_width, crop_height, x, y)?;
+        wand.reset_image_page(&format!("{crop_width}x{crop_height}+0+0"))?;
+    }
+    Ok(())
+}
+
+fn encode_jpeg(
+    wand: &mut MagickWand,
+    format: &FormatOptions,
+    compression_quality: usize,
+) -> Result<()> {
+    // ColorspaceType (MagickCore/colorspace.h): 3 = GRAYColorspace, 23 = sRGBColorspace
+    let colorspace = match format.jpeg_colorspace {
+        JpegColorspace::Keep => None,
+        JpegColorspace::Srgb => Some(23),
+        JpegColorspace::Gray => Some(3),
+    };
+    if let Some(colorspace) = colorspace {
+        wand.transform_image_colorspace(colorspace)?;
+        // A kept profile still describes the old colorspace
+        remove_image_profile(wand, "icc");
+    }
+    wand.set_sampling_factors(&format.jpeg_subsampling.factors())?;
     wand.set_image_compression_quality(compression_quality)?;
-    // 3 = Plane (build.rs)
-    wand.set_interlace_scheme(3)?;
-    // 26 should be RGB (have to build magick_rust myself to verify)
-    // wand.set_image_colorspace(30)?;
-    if apply_gaussian_blur {
-        // Pretty slow
-        wand.gaussian_blur_image(0.05, 1.0)?
+    // InterlaceType (MagickCore/image.h): 1 = NoInterlace, 3 = PlaneInterlace
+    wand.set_interlace_scheme(match format.jpeg_progressive {
+        true => 3,
+        false => 1,
+    })?;
+    wand.set_option(
+        "jpeg:optimize-coding",
+        &format.jpeg_optimize_coding.to_string(),
+    )?;
+    if format.jpeg_arithmetic_coding {
+        wand.set_option("jpeg:arithmetic-coding", "true")?;
     }
+    Ok(())
+}
 
-    wand.write_image(out_file)
+/// Lossless recompression and optional palette quantization, JPEG settings like chroma
+/// subsampling and interlacing only make PNGs larger. `image_colors` are the original's colors
+/// in `PngMode::Auto`
+fn encode_png(
+    wand: &mut MagickWand,
+    format: &FormatOptions,
+    image_colors: Option<usize>,
+) -> Result<()> {
+    let colors = format.png_colors.clamp(2, 256);
+    let quantize = match format.png_mode {
+        PngMode::Auto => image_colors.map_or(false, |image_colors| image_colors > colors),
+        PngMode::Lossless => false,
+        PngMode::Quantize => true,
+    };
+    if quantize {
+        // DitherMethod (MagickCore/quantize.h): 1 = NoDitherMethod, 3 = FloydSteinbergDitherMethod
+        let dither = match format.png_dither {
+            true => 3,
+            false => 1,
+        };
+        // Colorspace 0 = Undefined keeps the image's, tree depth 0 picks the best one
+        wand.quantize_image(
+            colors,
+            0,
+            0,
+            dither,
+            bindings::MagickBooleanType_MagickFalse,
+        )?;
+    }
+    // Lossless images keep their depth
+    if quantize && format.png_reduce_depth {
+        wand.set_image_depth(8)?;
+    }
+    wand.set_option(
+        "png:compression-level",
+        &format.png_compression_level.min(9).to_string(),
+    )?;
+    // 5 = adaptive filtering per row
+    wand.set_option("png:compression-filter", "5")?;
+    Ok(())
+}
+
+/// Convert the image to the output format, the file extension has to match for `write_image`
+fn apply_format(wand: &mut MagickWand, format: &FormatOptions) -> Result<()> {
+    let extension = match format.format.extension() {
+        Some(extension) => extension,
+        None => return Ok(()),
+    };
+    if !format.format.supports_alpha() {
+        let [r, g, b] = format.background;
+        let mut background = PixelWand::new();
+        background.set_color(&format!("#{r:02x}{g:02x}{b:02x}"))?;
+        wand.set_image_background_color(&background)?;
+        // 12 = RemoveAlphaChannel, blends transparent pixels with the background color
+        if wand.get_image_alpha_channel() {
+            wand.set_image_alpha_channel(12)?;
+        }
+    }
+    match format.format {
+        OutputFormat::WebP => {
+            wand.set_option("webp:lossless", &format.webp_lossless.to_string())?;
+        }
+        OutputFormat::Avif => {
+            wand.set_option("heic:speed", &format.avif_speed.min(10).to_string())?;
+        }
+        OutputFormat::Jxl => {
+            wand.set_option("jxl:effort", &format.jxl_effort.clamp(1, 9).to_string())?;
+        }
+        _ => {}
+    }
+    wand.set_image_format(extension)?;
+    Ok(())
 }
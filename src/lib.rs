@@ -1,5 +1,18 @@
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod exif;
+pub mod ffmpeg;
 pub mod filesystem;
 pub mod gui;
+pub mod icc;
 pub mod imagemagick;
+pub mod manifest;
+pub mod pipeline;
+pub mod preset;
+pub mod progress;
+pub mod report;
+pub mod template;
 pub mod threadpool;
 pub mod utils;
+pub mod watch;
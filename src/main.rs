@@ -1,9 +1,22 @@
+use clap::Parser;
 use eframe::{epaint::Vec2, NativeOptions};
-use rshrink::gui::RshrinkApp;
+use rshrink::{
+    cli::{self, Cli, Command},
+    gui::RshrinkApp,
+};
 
 const MIN_WIN_SIZE: Option<Vec2> = Some(Vec2::new(360.0, 300.0));
 
 fn main() {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Shrink(args)) => std::process::exit(cli::shrink(args)),
+        Some(Command::Gui) | None => run_gui(),
+    }
+}
+
+fn run_gui() {
     let native_options = NativeOptions {
         min_window_size: MIN_WIN_SIZE,
         ..Default::default()
@@ -0,0 +1,194 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, RshrinkError},
+    filesystem,
+    imagemagick::Similarity,
+    pipeline::{Job, LargerOutputPolicy, Options, Shrinked, Variant},
+};
+
+/// Written into every output folder of an incremental run, unless it's a source folder
+pub const MANIFEST_FILE_NAME: &str = ".rshrink-manifest.json";
+
+/// What an input looked like when it was shrinked and what came out of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    size: u64,
+    /// Nanoseconds since the unix epoch
+    modified: u128,
+    settings_hash: u64,
+    out_file: Option<PathBuf>,
+    new_size: u64,
+    quality: Option<usize>,
+    larger_output: Option<LargerOutputPolicy>,
+    /// Manifests written by an older version have no responsive sizes
+    #[serde(default)]
+    variants: Vec<Variant>,
+    #[serde(default)]
+    similarity: Option<Similarity>,
+    #[serde(default)]
+    original_dimensions: Option<(usize, usize)>,
+    #[serde(default)]
+    dimensions: Option<(usize, usize)>,
+}
+
+/// Shrinked files of a single output folder by their canonical input path
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Manifest {
+    /// A missing or unreadable manifest is treated as empty, the files are shrinked again then
+    fn load(dir: &Path) -> Manifest {
+        fs::read_to_string(dir.join(MANIFEST_FILE_NAME))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let json = serde_json::to_string(self)?;
+        fs::write(&path, json).map_err(|err| RshrinkError::io(path, err))
+    }
+}
+
+/// Manifests of all output folders of a batch, shared by the jobs running in the pool
+pub struct ManifestStore {
+    settings_hash: u64,
+    manifests: Mutex<HashMap<PathBuf, Manifest>>,
+}
+
+impl ManifestStore {
+    pub fn new(options: &Options) -> ManifestStore {
+        ManifestStore {
+            settings_hash: settings_hash(options),
+            manifests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Size of the input and its previous result, if neither the input nor the settings
+    /// changed since and the output still exists
+    pub fn lookup(&self, job: &Job) -> Option<(u64, Shrinked)> {
+        let (size, modified) = fingerprint(&job.in_file)?;
+        let key = fs::canonicalize(&job.in_file).ok()?;
+        let dir = manifest_dir(job)?;
+        let mut manifests = self.manifests.lock().ok()?;
+        let manifest = manifests
+            .entry(dir.clone())
+            .or_insert_with(|| Manifest::load(&dir));
+        let entry = manifest.entries.get(&key)?;
+
+        let up_to_date = entry.size == size
+            && entry.modified == modified
+            && entry.settings_hash == self.settings_hash
+            && entry
+                .out_file
+                .as_ref()
+                .map_or(true, |out_file| out_file.is_file())
+            && entry
+                .variants
+                .iter()
+                .all(|variant| variant.out_file.is_file());
+        match up_to_date {
+            true => Some((
+                size,
+                Shrinked {
+                    new_size: entry.new_size,
+                    quality: entry.quality,
+                    out_file: entry.out_file.clone(),
+                    larger_output: entry.larger_output,
+                    variants: entry.variants.clone(),
+                    similarity: entry.similarity,
+                    original_dimensions: entry.original_dimensions,
+                    dimensions: entry.dimensions,
+                },
+            )),
+            false => None,
+        }
+    }
+
+    pub fn record(&self, job: &Job, shrinked: &Shrinked) {
+        let (size, modified, key) =
+            match (fingerprint(&job.in_file), fs::canonicalize(&job.in_file)) {
+                (Some((size, modified)), Ok(key)) => (size, modified, key),
+                // Not recording a file only means it's shrinked again next time
+                _ => return,
+            };
+        let entry = Entry {
+            size,
+            modified,
+            settings_hash: self.settings_hash,
+            out_file: shrinked
+                .out_file
+                .as_ref()
+                .map(|out_file| fs::canonicalize(out_file).unwrap_or_else(|_| out_file.clone())),
+            new_size: shrinked.new_size,
+            quality: shrinked.quality,
+            larger_output: shrinked.larger_output,
+            variants: shrinked.variants.clone(),
+            similarity: shrinked.similarity,
+            original_dimensions: shrinked.original_dimensions,
+            dimensions: shrinked.dimensions,
+        };
+        if let (Some(dir), Ok(mut manifests)) = (manifest_dir(job), self.manifests.lock()) {
+            manifests
+                .entry(dir.clone())
+                .or_insert_with(|| Manifest::load(&dir))
+                .entries
+                .insert(key, entry);
+        }
+    }
+
+    /// Write all manifests that have been touched, has to be called once the batch is done
+    pub fn save(&self) -> Result<()> {
+        let manifests = match self.manifests.lock() {
+            Ok(manifests) => manifests,
+            // A job panicked while holding the lock, its manifest might be incomplete
+            Err(_) => return Ok(()),
+        };
+        for (dir, manifest) in manifests.iter() {
+            if dir.is_dir() {
+                manifest.save(dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Output folder of `job`, `None` if it's the input's folder. Source folders don't get a
+/// manifest, their files are shrinked again every time
+fn manifest_dir(job: &Job) -> Option<PathBuf> {
+    let dir = |file: &Path| match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let out_dir = dir(&job.out_file);
+    match filesystem::is_same_dir(&out_dir, &dir(&job.in_file)) {
+        true => None,
+        false => Some(out_dir),
+    }
+}
+
+/// Size and modification time in nanoseconds since the unix epoch
+fn fingerprint(file: &Path) -> Option<(u64, u128)> {
+    let (size, modified) = filesystem::fingerprint(file)?;
+    Some((size, modified.duration_since(UNIX_EPOCH).ok()?.as_nanos()))
+}
+
+/// FNV-1a of the serialized options, unlike `DefaultHasher` it's stable across Rust versions
+fn settings_hash(options: &Options) -> u64 {
+    let json = serde_json::to_string(options).unwrap_or_default();
+    json.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,1367 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, RshrinkError},
+    ffmpeg::{self, perform_ffmpeg},
+    filesystem::{
+        create_dir_if_not_exists, is_same_dir, is_video, list_files_recursive, replace_file,
+        temp_file, DEFAULT_OUT_DIR,
+    },
+    imagemagick::{
+        self, image_dimensions, perform_magick, perform_magick_target_size,
+        perform_magick_variants, FormatOptions, MagickOptions, MetadataOptions, Similarity,
+    },
+    manifest::ManifestStore,
+    template::{self, civil_date},
+    threadpool::ThreadPool,
+    utils::{Dimensions, ResizeMode},
+};
+
+/// Output file name if no template is set
+pub const DEFAULT_TEMPLATE: &str = "{name}";
+/// Output file names of responsive sizes if no template is set
+pub const DEFAULT_VARIANT_TEMPLATE: &str = "{stem}-{width}w.{ext}";
+/// Prefix of output files that would otherwise overwrite their original
+const SAME_DIR_PREFIX: &str = "min-";
+
+/// What to do if a shrinked file turns out larger than its original
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LargerOutputPolicy {
+    /// Write a copy of the original instead, with the original's extension
+    #[default]
+    CopyOriginal,
+    /// Don't write an output file at all
+    Skip,
+    /// Search a lower quality that ends up smaller, copy the original if there is none
+    Retry,
+}
+
+impl LargerOutputPolicy {
+    pub const ALL: [LargerOutputPolicy; 3] = [
+        LargerOutputPolicy::CopyOriginal,
+        LargerOutputPolicy::Skip,
+        LargerOutputPolicy::Retry,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LargerOutputPolicy::CopyOriginal => "Copy original",
+            LargerOutputPolicy::Skip => "Skip",
+            LargerOutputPolicy::Retry => "Retry lower quality",
+        }
+    }
+}
+
+impl std::str::FromStr for LargerOutputPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> std::result::Result<Self, Self::Err> {
+        match policy.to_lowercase().as_str() {
+            "copy" => Ok(LargerOutputPolicy::CopyOriginal),
+            "skip" => Ok(LargerOutputPolicy::Skip),
+            "retry" => Ok(LargerOutputPolicy::Retry),
+            _ => Err(format!(
+                "Unknown policy {policy}, expected copy, skip or retry"
+            )),
+        }
+    }
+}
+
+/// What to do if an output file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Collision {
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone and don't shrink the input
+    Skip,
+    /// Append `-1`, `-2`, ... to the file name until it's unique
+    Counter,
+}
+
+impl Collision {
+    pub const ALL: [Collision; 3] = [Collision::Overwrite, Collision::Skip, Collision::Counter];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Collision::Overwrite => "Overwrite",
+            Collision::Skip => "Skip",
+            Collision::Counter => "Append counter",
+        }
+    }
+}
+
+impl std::str::FromStr for Collision {
+    type Err = String;
+
+    fn from_str(collision: &str) -> std::result::Result<Self, Self::Err> {
+        match collision.to_lowercase().as_str() {
+            "overwrite" => Ok(Collision::Overwrite),
+            "skip" => Ok(Collision::Skip),
+            "counter" => Ok(Collision::Counter),
+            _ => Err(format!(
+                "Unknown collision handling {collision}, expected overwrite, skip or counter"
+            )),
+        }
+    }
+}
+
+/// Where originals are kept when they're replaced by their shrinked version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backup {
+    /// Replaced originals are gone
+    #[default]
+    None,
+    /// Keep the original next to it with a `.bak` suffix
+    Suffix,
+    /// Move originals into a backup folder, mirroring their directory structure
+    Dir,
+}
+
+impl Backup {
+    pub const ALL: [Backup; 3] = [Backup::None, Backup::Suffix, Backup::Dir];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backup::None => "None",
+            Backup::Suffix => ".bak file",
+            Backup::Dir => "Backup folder",
+        }
+    }
+}
+
+/// Description of the responsive sizes of an image, written next to them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Srcset {
+    #[default]
+    None,
+    /// `<img>` tag with a `srcset` attribute
+    Html,
+    /// List of the written files with their dimensions and sizes
+    Json,
+}
+
+impl Srcset {
+    pub const ALL: [Srcset; 3] = [Srcset::None, Srcset::Html, Srcset::Json];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Srcset::None => "None",
+            Srcset::Html => "HTML srcset",
+            Srcset::Json => "JSON",
+        }
+    }
+}
+
+impl std::str::FromStr for Srcset {
+    type Err = String;
+
+    fn from_str(srcset: &str) -> std::result::Result<Self, Self::Err> {
+        match srcset.to_lowercase().as_str() {
+            "none" => Ok(Srcset::None),
+            "html" => Ok(Srcset::Html),
+            "json" => Ok(Srcset::Json),
+            _ => Err(format!(
+                "Unknown srcset {srcset}, expected none, html or json"
+            )),
+        }
+    }
+}
+
+/// Settings applied to every file of a batch
+#[derive(Debug, Clone, Serialize)]
+pub struct Options {
+    /// Fit images into these dimensions, keep the original size if `None`
+    pub dimensions: Option<Dimensions>,
+    /// Write every image in each of these sizes instead of `dimensions`, read only once
+    pub variants: Vec<Dimensions>,
+    pub srcset: Srcset,
+    pub compression_quality: usize,
+    pub apply_gaussian_blur: bool,
+    /// Search the highest quality that results in at most this many bytes instead of using
+    /// `compression_quality`, only applies to images
+    pub target_size: Option<u64>,
+    /// Reduce the dimensions if the lowest quality still exceeds `target_size`
+    pub target_size_resize: bool,
+    /// Compute SSIM and PSNR of every shrinked image
+    pub metrics: bool,
+    /// Search the lowest quality whose SSIM reaches this instead of using `compression_quality`,
+    /// only applies to images
+    pub min_ssim: Option<f64>,
+    /// Convert images into another format, videos keep theirs
+    pub format: FormatOptions,
+    /// Metadata kept in images, videos drop all metadata
+    pub metadata: MetadataOptions,
+    pub larger_output: LargerOutputPolicy,
+    /// Name of the output folder, files are written next to the original if empty
+    pub output_folder_name: String,
+    /// Create the output folder in this directory instead of next to each file
+    pub output_folder_parent_dir: Option<PathBuf>,
+    /// Path of the output files relative to the output folder, see `template::PLACEHOLDERS`.
+    /// `DEFAULT_TEMPLATE` if `None`
+    pub file_name_template: Option<String>,
+    pub collision: Collision,
+    /// Name of the selected preset for the `{preset}` placeholder
+    #[serde(skip)]
+    pub preset_name: Option<String>,
+    /// Overwrite the originals instead of writing into the output folder
+    pub replace_originals: bool,
+    pub backup: Backup,
+    /// Folder for `Backup::Dir`, originals get a `.bak` suffix instead if `None`
+    pub backup_dir: Option<PathBuf>,
+    /// Skip files that didn't change since they were shrinked with the same options, recorded in
+    /// a manifest inside the output folder. Ignored for files written into their own folder, the
+    /// source folders stay free of manifests
+    #[serde(skip)]
+    pub incremental: bool,
+}
+
+impl Options {
+    /// Output folder for files of `dir`, or for the whole tree if `dir` is an input root
+    pub fn out_dir(&self, dir: &Path) -> PathBuf {
+        let mut out_dir = match &self.output_folder_parent_dir {
+            Some(parent_dir) => parent_dir.clone(),
+            None => dir.to_path_buf(),
+        };
+        if !self.output_folder_name.is_empty() {
+            out_dir.push(&self.output_folder_name);
+        }
+        out_dir
+    }
+
+    /// Folder below `root` that holds results instead of inputs, skipped when listing files
+    pub fn excluded_dir(&self, root: &Path) -> Option<PathBuf> {
+        match (self.replace_originals, self.backup) {
+            (true, Backup::Dir) => self.backup_dir.clone(),
+            (true, _) => None,
+            (false, _) => Some(self.out_dir(root)),
+        }
+    }
+
+    /// Check what can come from presets or config files without being validated, a template
+    /// must not leave the output folder
+    pub fn validate(&self) -> Result<()> {
+        // Presets and config files aren't checked like --target-size
+        if self.target_size == Some(0) {
+            return Err(RshrinkError::InvalidOptions(String::from(
+                "The target size has to be at least 1 Kb",
+            )));
+        }
+        // An original is replaced by a single file with its own name
+        if self.replace_originals && !self.variants.is_empty() {
+            return Err(RshrinkError::InvalidOptions(String::from(
+                "Originals can't be replaced by several sizes",
+            )));
+        }
+        match (&self.file_name_template, self.replace_originals) {
+            (Some(_), true) => Err(RshrinkError::InvalidOptions(String::from(
+                "Replaced originals keep their name, a file name template can't be used",
+            ))),
+            (Some(template), false) => template::validate(template),
+            (None, _) => Ok(()),
+        }
+    }
+
+    pub fn magick_options(&self) -> MagickOptions {
+        MagickOptions {
+            dims: self.dimensions.clone(),
+            compression_quality: self.compression_quality,
+            apply_gaussian_blur: self.apply_gaussian_blur,
+            format: self.format.clone(),
+            metadata: self.metadata.clone(),
+            metrics: self.metrics,
+            min_ssim: self.min_ssim,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            dimensions: None,
+            variants: Vec::new(),
+            srcset: Srcset::default(),
+            compression_quality: 85,
+            apply_gaussian_blur: false,
+            target_size: None,
+            target_size_resize: false,
+            metrics: false,
+            min_ssim: None,
+            format: FormatOptions::default(),
+            metadata: MetadataOptions::default(),
+            larger_output: LargerOutputPolicy::default(),
+            output_folder_name: String::from(DEFAULT_OUT_DIR),
+            output_folder_parent_dir: None,
+            file_name_template: None,
+            collision: Collision::default(),
+            preset_name: None,
+            replace_originals: false,
+            backup: Backup::default(),
+            backup_dir: None,
+            incremental: true,
+        }
+    }
+}
+
+/// A single file of a batch
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub in_file: PathBuf,
+    pub out_file: PathBuf,
+    /// Where the original is kept once it's replaced by `out_file`
+    pub backup_file: Option<PathBuf>,
+    /// Responsive sizes written instead of a single file, `out_file` is the first one's path.
+    /// Empty for videos
+    pub variants: Vec<(Dimensions, PathBuf)>,
+}
+
+impl Job {
+    /// Compute the output path of the `index`th file of a batch according to `options`
+    pub fn new(in_file: PathBuf, index: usize, options: &Options) -> Job {
+        let parent = parent_dir(&in_file);
+        let out_dir = options.out_dir(&parent);
+        Job::with_out_dir(in_file, index, out_dir, &parent, Path::new(""), options)
+    }
+
+    /// Compute the output path of `in_file` found below `root`, its subdirectories relative to
+    /// `root` are recreated inside a single output folder
+    pub fn in_tree(in_file: PathBuf, index: usize, root: &Path, options: &Options) -> Job {
+        let parent = parent_dir(&in_file);
+        let relative_dir = parent.strip_prefix(root).unwrap_or_else(|_| Path::new(""));
+        let out_dir = options.out_dir(root).join(relative_dir);
+        Job::with_out_dir(in_file, index, out_dir, &parent, relative_dir, options)
+    }
+
+    fn with_out_dir(
+        in_file: PathBuf,
+        index: usize,
+        out_dir: PathBuf,
+        parent: &Path,
+        relative_dir: &Path,
+        options: &Options,
+    ) -> Job {
+        let mut file_name = PathBuf::from(in_file.file_name().unwrap_or_default());
+        if let Some(extension) = options.format.format.extension() {
+            if !is_video(&in_file) {
+                file_name.set_extension(extension);
+            }
+        }
+        if options.replace_originals {
+            let backup_file = match (options.backup, &options.backup_dir) {
+                (Backup::None, _) => None,
+                (Backup::Dir, Some(backup_dir)) => Some(
+                    backup_dir
+                        .join(relative_dir)
+                        .join(in_file.file_name().unwrap_or_default()),
+                ),
+                (Backup::Suffix | Backup::Dir, _) => {
+                    let mut name = in_file.clone().into_os_string();
+                    name.push(".bak");
+                    Some(PathBuf::from(name))
+                }
+            };
+            return Job {
+                out_file: parent.join(file_name),
+                in_file,
+                backup_file,
+                variants: Vec::new(),
+            };
+        }
+
+        let render = |template: &str, dims: Option<&Dimensions>| {
+            let mut out_file = out_dir.join(template::render(template, |placeholder| {
+                placeholder_value(placeholder, &in_file, &file_name, index, dims, options)
+            }));
+            // Don't overwrite the original if the output folder is the input folder
+            if out_file.file_name() == in_file.file_name()
+                && is_same_dir(&parent_dir(&out_file), parent)
+            {
+                let mut name = OsString::from(SAME_DIR_PREFIX);
+                name.push(out_file.file_name().unwrap_or_default());
+                out_file.set_file_name(name);
+            }
+            out_file
+        };
+        let variants = match is_video(&in_file) {
+            true => Vec::new(),
+            false => {
+                let template = options
+                    .file_name_template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_VARIANT_TEMPLATE);
+                let mut taken = HashSet::new();
+                options
+                    .variants
+                    .iter()
+                    .map(|dims| {
+                        // A template without {width} would give every size the same name
+                        let out_file =
+                            unique_path(&render(template, Some(dims)), |path| taken.contains(path));
+                        taken.insert(out_file.clone());
+                        (dims.clone(), out_file)
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        let out_file = match variants.first() {
+            Some((_, out_file)) => out_file.clone(),
+            None => render(
+                options
+                    .file_name_template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_TEMPLATE),
+                options.dimensions.as_ref(),
+            ),
+        };
+
+        Job {
+            in_file,
+            out_file,
+            backup_file: None,
+            variants,
+        }
+    }
+
+    /// Every file the job writes
+    pub fn out_files(&self) -> Vec<&Path> {
+        match self.variants.is_empty() {
+            true => vec![self.out_file.as_path()],
+            false => self
+                .variants
+                .iter()
+                .map(|(_, out_file)| out_file.as_path())
+                .collect(),
+        }
+    }
+}
+
+/// Result of a successfully shrinked file
+#[derive(Debug)]
+pub struct Shrinked {
+    /// Size of the output, the original's size if it was skipped
+    pub new_size: u64,
+    /// Quality the image was encoded with, `None` for videos and copied originals
+    pub quality: Option<usize>,
+    /// File actually written, differs from the job's `out_file` if the original was copied
+    /// into a converted format's place. `None` if it was skipped
+    pub out_file: Option<PathBuf>,
+    /// Policy that was applied because the first result was larger than the original
+    pub larger_output: Option<LargerOutputPolicy>,
+    /// Responsive sizes that were written, `new_size` is their total
+    pub variants: Vec<Variant>,
+    /// Compared with the original if metrics are enabled, `None` for videos and copied originals
+    pub similarity: Option<Similarity>,
+    /// Width and height of the original image as it's shown, `None` for videos
+    pub original_dimensions: Option<(usize, usize)>,
+    /// Width and height of the written image or its first size, `None` for videos and skipped
+    /// files
+    pub dimensions: Option<(usize, usize)>,
+}
+
+/// A single responsive size of an image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    pub out_file: PathBuf,
+    pub width: usize,
+    pub height: usize,
+    pub size: u64,
+    /// `None` for a copy of the original
+    pub quality: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<Similarity>,
+}
+
+/// What happened to a single job
+#[derive(Debug)]
+pub enum Outcome {
+    Done(Shrinked),
+    /// Input and options didn't change since the last incremental run, holds its result
+    UpToDate(Shrinked),
+    Failed(RshrinkError),
+    /// The batch was cancelled before the job started
+    Cancelled,
+    /// The output file already existed and `Collision::Skip` is set, nothing was written
+    Exists,
+}
+
+/// Outcome of a single job
+#[derive(Debug)]
+pub struct JobResult {
+    /// Position of the job in the batch
+    pub index: usize,
+    pub in_file: PathBuf,
+    pub out_file: PathBuf,
+    pub size: u64,
+    pub outcome: Outcome,
+    /// Time spent on the job, including the manifest lookup
+    pub duration: Duration,
+}
+
+impl JobResult {
+    /// Size of the written files, `None` if the job failed or nothing was written
+    pub fn new_size(&self) -> Option<u64> {
+        match &self.outcome {
+            Outcome::Done(shrinked) | Outcome::UpToDate(shrinked) => Some(shrinked.new_size),
+            _ => None,
+        }
+    }
+}
+
+/// Sent by the pipeline while a batch is running
+#[derive(Debug)]
+pub enum Event {
+    /// Sent for every job when the batch is dispatched, before any of them starts
+    Queued { index: usize },
+    /// A worker picked up the job, not sent for cancelled jobs
+    Started { index: usize },
+    /// Fraction of a video that has been processed so far, images don't report progress
+    Progress { index: usize, fraction: f32 },
+    /// Sent exactly once per job, failed jobs included
+    Finished(JobResult),
+}
+
+/// Shared flag to stop a running batch, cheap to clone and hand to other threads
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A batch of files shrinked with the same options
+pub struct Pipeline {
+    jobs: Vec<Job>,
+    options: Arc<Options>,
+    cancellation_token: CancellationToken,
+}
+
+impl Pipeline {
+    pub fn new<P: Into<PathBuf>>(
+        paths: impl IntoIterator<Item = P>,
+        options: Options,
+    ) -> Result<Pipeline> {
+        let jobs = paths
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| Job::new(path.into(), index, &options))
+            .collect();
+        Pipeline::from_jobs(jobs, options)
+    }
+
+    /// Shrink every file below `root` whose name matches `file_sel`, mirroring the directory
+    /// structure in the output folder
+    pub fn from_dir(root: &Path, file_sel: &str, options: Options) -> Result<Pipeline> {
+        // The output folder might be inside the tree, don't shrink the results of a previous run
+        let files = list_files_recursive(root, file_sel, options.excluded_dir(root).as_deref())?;
+        let jobs = files
+            .into_iter()
+            .enumerate()
+            .map(|(index, file)| Job::in_tree(file, index, root, &options))
+            .collect();
+        Pipeline::from_jobs(jobs, options)
+    }
+
+    /// Use jobs created with `Job::new` or `Job::in_tree` for the same `options`, fails if the
+    /// options are invalid
+    pub fn from_jobs(mut jobs: Vec<Job>, options: Options) -> Result<Pipeline> {
+        options.validate()?;
+        match (options.replace_originals, options.collision) {
+            (true, collision) => unique_in_place_paths(&mut jobs, collision == Collision::Counter),
+            (false, collision) => {
+                avoid_inputs(&mut jobs);
+                if collision == Collision::Counter {
+                    number_collisions(&mut jobs);
+                }
+            }
+        }
+        Ok(Pipeline {
+            jobs,
+            options: Arc::new(options),
+            cancellation_token: CancellationToken::new(),
+        })
+    }
+
+    /// Use `cancellation_token` instead of the pipeline's own token
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Pipeline {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Stop the batch: jobs still queued in the pool are drained without touching any file and
+    /// report `Outcome::Cancelled`, jobs that are already running finish normally
+    pub fn cancel(&self) {
+        self.cancellation_token.cancel();
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Dispatch all jobs to `thread_pool`, one `Event::Finished` per job is sent over the
+    /// returned receiver as soon as it's done. All `Event::Queued` are sent before that
+    pub fn run(&self, thread_pool: &ThreadPool) -> mpsc::Receiver<Event> {
+        imagemagick::init();
+        ffmpeg::init();
+
+        let (sender, receiver) = mpsc::channel();
+        let manifests = match self.options.incremental && !self.options.replace_originals {
+            true => Some(Arc::new(ManifestStore::new(&self.options))),
+            false => None,
+        };
+        let remaining = Arc::new(AtomicUsize::new(self.jobs.len()));
+        for index in 0..self.jobs.len() {
+            let _ = sender.send(Event::Queued { index });
+        }
+        for (index, job) in self.jobs.iter().enumerate() {
+            let job = job.clone();
+            let options = Arc::clone(&self.options);
+            let cancellation_token = self.cancellation_token.clone();
+            let manifests = manifests.clone();
+            let remaining = Arc::clone(&remaining);
+            let sender = sender.clone();
+            thread_pool.execute(move || {
+                let started = Instant::now();
+                let cancelled = cancellation_token.is_cancelled();
+                if !cancelled {
+                    let _ = sender.send(Event::Started { index });
+                }
+                let (size, outcome) = match cancelled {
+                    true => (0, Outcome::Cancelled),
+                    false => match manifests
+                        .as_ref()
+                        .and_then(|manifests| manifests.lookup(&job))
+                    {
+                        Some((size, shrinked)) => (size, Outcome::UpToDate(shrinked)),
+                        // In place only converted files can end up on another file
+                        None if options.collision == Collision::Skip
+                            && (!options.replace_originals || job.out_file != job.in_file)
+                            && job.out_file.exists() =>
+                        {
+                            let size =
+                                fs::metadata(&job.in_file).map_or(0, |metadata| metadata.len());
+                            (size, Outcome::Exists)
+                        }
+                        None => run_job(&job, index, &options, |fraction| {
+                            let _ = sender.send(Event::Progress { index, fraction });
+                        }),
+                    },
+                };
+                if let Some(manifests) = &manifests {
+                    if let Outcome::Done(shrinked) = &outcome {
+                        manifests.record(&job, shrinked);
+                    }
+                    // The last job writes the manifests before the receiver disconnects
+                    if remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        if let Err(err) = manifests.save() {
+                            eprintln!("Failed to write manifest! {err}");
+                        }
+                    }
+                }
+                // With `Collision::Counter` the previous output got the job another name, which
+                // isn't written if it's up to date
+                let out_file = match &outcome {
+                    Outcome::UpToDate(Shrinked {
+                        out_file: Some(out_file),
+                        ..
+                    }) => out_file.clone(),
+                    _ => job.out_file,
+                };
+                // Receiver might have been dropped, nobody is interested in the result then
+                let _ = sender.send(Event::Finished(JobResult {
+                    index,
+                    in_file: job.in_file,
+                    out_file,
+                    size,
+                    outcome,
+                    duration: started.elapsed(),
+                }));
+            });
+        }
+
+        receiver
+    }
+}
+
+fn run_job<F>(job: &Job, index: usize, options: &Options, progress: F) -> (u64, Outcome)
+where
+    F: FnMut(f32),
+{
+    let size = match fs::metadata(&job.in_file) {
+        Ok(metadata) => metadata.len(),
+        Err(err) => return (0, Outcome::Failed(RshrinkError::io(&job.in_file, err))),
+    };
+    // Originals are only replaced by complete files, they're written next to them first
+    let written = match options.replace_originals {
+        true => Job {
+            out_file: temp_file(&job.out_file, index),
+            ..job.clone()
+        },
+        false => job.clone(),
+    };
+    match shrink_job(&written, options, progress)
+        .and_then(|shrinked| apply_larger_output_policy(&written, options, size, shrinked))
+        .and_then(|shrinked| match options.replace_originals {
+            true => replace_original(job, shrinked),
+            false => Ok(shrinked),
+        }) {
+        Ok(shrinked) => (size, Outcome::Done(shrinked)),
+        Err(err) => {
+            // Don't leave a partially written file behind
+            for out_file in written.out_files() {
+                remove_partial_output(out_file);
+            }
+            (size, Outcome::Failed(err))
+        }
+    }
+}
+
+/// Move the shrinked file over the original, unless the original is kept anyway
+fn replace_original(job: &Job, shrinked: Shrinked) -> Result<Shrinked> {
+    match (&shrinked.out_file, shrinked.larger_output) {
+        // Skipped, the original stays where it is
+        (None, _) => Ok(shrinked),
+        // A copy of the original would replace it with itself
+        (Some(out_file), Some(LargerOutputPolicy::CopyOriginal)) => {
+            fs::remove_file(out_file).map_err(|err| RshrinkError::io(out_file, err))?;
+            Ok(Shrinked {
+                out_file: Some(job.in_file.clone()),
+                ..shrinked
+            })
+        }
+        (Some(out_file), _) => {
+            replace_file(
+                &job.in_file,
+                out_file,
+                &job.out_file,
+                job.backup_file.as_deref(),
+            )?;
+            Ok(Shrinked {
+                out_file: Some(job.out_file.clone()),
+                ..shrinked
+            })
+        }
+    }
+}
+
+fn shrink_job<F>(job: &Job, options: &Options, progress: F) -> Result<Shrinked>
+where
+    F: FnMut(f32),
+{
+    let in_path = job
+        .in_file
+        .to_str()
+        .ok_or_else(|| RshrinkError::InvalidPath(job.in_file.clone()))?;
+    if !job.variants.is_empty() {
+        return shrink_variants(job, in_path, options);
+    }
+    let out_path = job
+        .out_file
+        .to_str()
+        .ok_or_else(|| RshrinkError::InvalidPath(job.out_file.clone()))?;
+    if let Some(out_dir) = job.out_file.parent() {
+        create_dir_if_not_exists(&out_dir.to_string_lossy())?;
+    }
+
+    let written = match (is_video(&job.in_file), options.target_size) {
+        (true, _) => {
+            perform_ffmpeg(
+                in_path,
+                out_path,
+                options.dimensions.as_ref(),
+                options.compression_quality,
+                progress,
+            )?;
+            None
+        }
+        (false, Some(target_size)) => Some(perform_magick_target_size(
+            in_path,
+            out_path,
+            &options.magick_options(),
+            target_size,
+            options.target_size_resize,
+        )?),
+        (false, None) => Some(perform_magick(
+            in_path,
+            out_path,
+            &options.magick_options(),
+        )?),
+    };
+
+    // Read file metadata to determine new file size
+    let metadata =
+        fs::metadata(&job.out_file).map_err(|err| RshrinkError::io(&job.out_file, err))?;
+    Ok(Shrinked {
+        new_size: metadata.len(),
+        quality: written.map(|written| written.quality),
+        out_file: Some(job.out_file.clone()),
+        larger_output: None,
+        variants: Vec::new(),
+        similarity: written.and_then(|written| written.similarity),
+        original_dimensions: written.and_then(|_| image_dimensions(in_path)),
+        dimensions: written.map(|written| (written.width, written.height)),
+    })
+}
+
+/// Write every responsive size of `job`, the input is only read once
+fn shrink_variants(job: &Job, in_path: &str, options: &Options) -> Result<Shrinked> {
+    let mut out_paths = Vec::new();
+    for (dims, out_file) in &job.variants {
+        let out_path = out_file
+            .to_str()
+            .ok_or_else(|| RshrinkError::InvalidPath(out_file.clone()))?;
+        if let Some(out_dir) = out_file.parent() {
+            create_dir_if_not_exists(&out_dir.to_string_lossy())?;
+        }
+        out_paths.push((dims, out_path));
+    }
+    let written = perform_magick_variants(
+        in_path,
+        &out_paths,
+        &options.magick_options(),
+        options.target_size,
+    )?;
+
+    // Sizes as wide as an earlier one weren't written
+    let variants = job
+        .variants
+        .iter()
+        .zip(written)
+        .filter_map(|((_, out_file), written)| Some((out_file, written?)))
+        .map(|(out_file, written)| {
+            let metadata = fs::metadata(out_file).map_err(|err| RshrinkError::io(out_file, err))?;
+            Ok(Variant {
+                out_file: out_file.clone(),
+                width: written.width,
+                height: written.height,
+                size: metadata.len(),
+                quality: Some(written.quality),
+                similarity: written.similarity,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Shrinked {
+        new_size: variants.iter().map(|variant| variant.size).sum(),
+        quality: variants.first().and_then(|variant| variant.quality),
+        out_file: Some(job.out_file.clone()),
+        larger_output: None,
+        similarity: variants.first().and_then(|variant| variant.similarity),
+        original_dimensions: image_dimensions(in_path),
+        dimensions: variants
+            .first()
+            .map(|variant| (variant.width, variant.height)),
+        variants,
+    })
+}
+
+/// Describe the written sizes for a web page in `{stem}.srcset.html` or `.json`, next to the
+/// first size. Paths are relative to that file
+fn write_srcset(job: &Job, variants: &[Variant], srcset: Srcset) -> Result<()> {
+    let extension = match srcset {
+        Srcset::None => return Ok(()),
+        Srcset::Html => "html",
+        Srcset::Json => "json",
+    };
+    let dir = parent_dir(&job.out_file);
+    let mut name = job.in_file.file_stem().unwrap_or_default().to_owned();
+    name.push(format!(".srcset.{extension}"));
+    let path = dir.join(name);
+    let relative = |variant: &Variant| {
+        variant
+            .out_file
+            .strip_prefix(&dir)
+            .unwrap_or(&variant.out_file)
+            .to_string_lossy()
+            .replace('\\', "/")
+    };
+
+    let content = match srcset {
+        Srcset::Json => serde_json::to_string_pretty(
+            &variants
+                .iter()
+                .map(|variant| Variant {
+                    out_file: PathBuf::from(relative(variant)),
+                    ..variant.clone()
+                })
+                .collect::<Vec<_>>(),
+        )?,
+        _ => {
+            // The largest size is the fallback for browsers without srcset
+            let largest = match variants.iter().max_by_key(|variant| variant.width) {
+                Some(largest) => largest,
+                None => return Ok(()),
+            };
+            let candidates = variants
+                .iter()
+                .map(|variant| format!("{} {}w", url(&relative(variant)), variant.width))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "<img src=\"{}\" srcset=\"{candidates}\" sizes=\"100vw\" width=\"{}\" height=\"{}\" alt=\"\">\n",
+                url(&relative(largest)),
+                largest.width,
+                largest.height
+            )
+        }
+    };
+    fs::write(&path, content).map_err(|err| RshrinkError::io(path, err))
+}
+
+/// Escape a relative path for an HTML attribute, spaces and commas would split srcset candidates
+fn url(path: &str) -> String {
+    path.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace(' ', "%20")
+        .replace(',', "%2C")
+}
+
+fn apply_larger_output_policy(
+    job: &Job,
+    options: &Options,
+    size: u64,
+    shrinked: Shrinked,
+) -> Result<Shrinked> {
+    if !shrinked.variants.is_empty() {
+        return apply_larger_variants_policy(job, options, size, shrinked);
+    }
+    if shrinked.new_size <= size {
+        return Ok(shrinked);
+    }
+
+    let policy = match (options.larger_output, is_video(&job.in_file)) {
+        // Videos have no quality search
+        (LargerOutputPolicy::Retry, true) => LargerOutputPolicy::CopyOriginal,
+        (policy, _) => policy,
+    };
+    match policy {
+        LargerOutputPolicy::CopyOriginal => copy_original(job, size, shrinked.original_dimensions),
+        LargerOutputPolicy::Skip => {
+            fs::remove_file(&job.out_file).map_err(|err| RshrinkError::io(&job.out_file, err))?;
+            Ok(Shrinked {
+                new_size: size,
+                quality: None,
+                out_file: None,
+                larger_output: Some(LargerOutputPolicy::Skip),
+                variants: Vec::new(),
+                similarity: None,
+                original_dimensions: shrinked.original_dimensions,
+                dimensions: None,
+            })
+        }
+        LargerOutputPolicy::Retry => {
+            // Paths were already validated by shrink_job
+            let (in_path, out_path) = (
+                job.in_file.to_string_lossy(),
+                job.out_file.to_string_lossy(),
+            );
+            match perform_magick_target_size(
+                &in_path,
+                &out_path,
+                &options.magick_options(),
+                size.saturating_sub(1),
+                false,
+            ) {
+                Ok(written) => {
+                    let metadata = fs::metadata(&job.out_file)
+                        .map_err(|err| RshrinkError::io(&job.out_file, err))?;
+                    Ok(Shrinked {
+                        new_size: metadata.len(),
+                        quality: Some(written.quality),
+                        out_file: Some(job.out_file.clone()),
+                        larger_output: Some(LargerOutputPolicy::Retry),
+                        variants: Vec::new(),
+                        similarity: written.similarity,
+                        original_dimensions: shrinked.original_dimensions,
+                        dimensions: Some((written.width, written.height)),
+                    })
+                }
+                Err(RshrinkError::TargetSizeUnreachable { .. }) => {
+                    copy_original(job, size, shrinked.original_dimensions)
+                }
+                Err(err) => Err(err),
+            }
+        }
+    }
+}
+
+/// Apply the policy to every size on its own, compared with the original. All sizes together are
+/// often larger than the original, that's the point of them. The srcset only lists the sizes
+/// that are left, each width once
+fn apply_larger_variants_policy(
+    job: &Job,
+    options: &Options,
+    size: u64,
+    shrinked: Shrinked,
+) -> Result<Shrinked> {
+    let original_dimensions = shrinked.original_dimensions;
+    let copy = |variant: &Variant| -> Result<Variant> {
+        let (width, height) = original_dimensions.unwrap_or((variant.width, variant.height));
+        Ok(Variant {
+            out_file: copy_original_file(&job.in_file, &variant.out_file)?,
+            width,
+            height,
+            size,
+            quality: None,
+            similarity: None,
+        })
+    };
+    let mut variants = Vec::new();
+    let mut larger_output = None;
+    for variant in shrinked.variants {
+        if variant.size <= size {
+            variants.push(variant);
+            continue;
+        }
+        larger_output = Some(options.larger_output);
+        match options.larger_output {
+            LargerOutputPolicy::CopyOriginal => variants.push(copy(&variant)?),
+            LargerOutputPolicy::Skip => fs::remove_file(&variant.out_file)
+                .map_err(|err| RshrinkError::io(&variant.out_file, err))?,
+            LargerOutputPolicy::Retry => {
+                let dims = job
+                    .variants
+                    .iter()
+                    .find(|(_, out_file)| *out_file == variant.out_file)
+                    .map(|(dims, _)| dims.clone());
+                let magick_options = MagickOptions {
+                    dims,
+                    ..options.magick_options()
+                };
+                // Paths were already validated by shrink_variants
+                match perform_magick_target_size(
+                    &job.in_file.to_string_lossy(),
+                    &variant.out_file.to_string_lossy(),
+                    &magick_options,
+                    size.saturating_sub(1),
+                    false,
+                ) {
+                    Ok(written) => {
+                        let metadata = fs::metadata(&variant.out_file)
+                            .map_err(|err| RshrinkError::io(&variant.out_file, err))?;
+                        variants.push(Variant {
+                            width: written.width,
+                            height: written.height,
+                            size: metadata.len(),
+                            quality: Some(written.quality),
+                            similarity: written.similarity,
+                            ..variant
+                        });
+                    }
+                    Err(RshrinkError::TargetSizeUnreachable { .. }) => {
+                        variants.push(copy(&variant)?)
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+    // Copies of the original all have its width
+    let mut widths = HashSet::new();
+    let mut unique = Vec::new();
+    for variant in variants {
+        match widths.insert(variant.width) {
+            true => unique.push(variant),
+            false => fs::remove_file(&variant.out_file)
+                .map_err(|err| RshrinkError::io(&variant.out_file, err))?,
+        }
+    }
+    let variants = unique;
+    write_srcset(job, &variants, options.srcset)?;
+    Ok(Shrinked {
+        // Like a skipped file if no size is left
+        new_size: match variants.is_empty() {
+            true => size,
+            false => variants.iter().map(|variant| variant.size).sum(),
+        },
+        quality: variants.first().and_then(|variant| variant.quality),
+        out_file: variants.first().map(|variant| variant.out_file.clone()),
+        larger_output,
+        similarity: variants.first().and_then(|variant| variant.similarity),
+        original_dimensions,
+        dimensions: variants
+            .first()
+            .map(|variant| (variant.width, variant.height)),
+        variants,
+    })
+}
+
+/// Replace the output with the original, a converted output keeps the original's format
+fn copy_original(
+    job: &Job,
+    size: u64,
+    original_dimensions: Option<(usize, usize)>,
+) -> Result<Shrinked> {
+    let out_file = copy_original_file(&job.in_file, &job.out_file)?;
+    Ok(Shrinked {
+        new_size: size,
+        quality: None,
+        out_file: Some(out_file),
+        larger_output: Some(LargerOutputPolicy::CopyOriginal),
+        variants: Vec::new(),
+        similarity: None,
+        original_dimensions,
+        dimensions: original_dimensions,
+    })
+}
+
+/// Replace `out_file` with a copy of `in_file` with the original's extension, returns the copy's
+/// path. If that's the original's own path it's only returned, not copied
+fn copy_original_file(in_file: &Path, out_file: &Path) -> Result<PathBuf> {
+    let copy = match in_file.extension() {
+        Some(extension) => out_file.with_extension(extension),
+        None => out_file.to_path_buf(),
+    };
+    if copy != out_file {
+        fs::remove_file(out_file).map_err(|err| RshrinkError::io(out_file, err))?;
+    }
+    // Converted next to the original, copying it onto itself would truncate it
+    let is_original = copy.file_name() == in_file.file_name()
+        && is_same_dir(&parent_dir(&copy), &parent_dir(in_file));
+    if !is_original {
+        fs::copy(in_file, &copy).map_err(|err| RshrinkError::io(&copy, err))?;
+    }
+    Ok(copy)
+}
+
+fn remove_partial_output(out_file: &Path) {
+    if out_file.is_file() {
+        // The job already failed, the original error is more useful than this one
+        let _ = fs::remove_file(out_file);
+    }
+}
+
+/// Value of a file name template placeholder for the `index`th input `in_file`, which is written
+/// as `file_name` scaled to `dims`
+fn placeholder_value(
+    placeholder: &str,
+    in_file: &Path,
+    file_name: &Path,
+    index: usize,
+    dims: Option<&Dimensions>,
+    options: &Options,
+) -> Option<String> {
+    let os_str =
+        |value: Option<&std::ffi::OsStr>| value.unwrap_or_default().to_string_lossy().into_owned();
+    // Files are kept at their size without dimensions
+    let side = |side: fn(&Dimensions) -> usize| match dims {
+        Some(dims) => match dims.mode {
+            ResizeMode::Percent => format!("{}pct", dims.percent),
+            ResizeMode::LongEdge => dims.long_edge.to_string(),
+            _ => side(dims).to_string(),
+        },
+        None => String::from("orig"),
+    };
+    Some(match placeholder {
+        "name" => os_str(Some(file_name.as_os_str())),
+        "stem" => os_str(file_name.file_stem()),
+        "ext" => os_str(file_name.extension()),
+        "width" => side(|dims| dims.width),
+        "height" => side(|dims| dims.height),
+        // Searched per file in max file size and min SSIM mode
+        "quality" => match (options.target_size, options.min_ssim) {
+            (None, None) => options.compression_quality.to_string(),
+            _ => String::from("auto"),
+        },
+        // Names are typed in the UI, they can't add folders
+        "preset" => options
+            .preset_name
+            .as_deref()
+            .unwrap_or("custom")
+            .replace(['/', '\\'], "_")
+            .replace("..", "_"),
+        "index" => (index + 1).to_string(),
+        "date" => {
+            // Modification date of the input, today if it can't be read
+            let modified = fs::metadata(in_file)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            let days = modified
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs() / 86400);
+            let (year, month, day) = civil_date(days as i64);
+            format!("{year:04}-{month:02}-{day:02}")
+        }
+        _ => return None,
+    })
+}
+
+/// Append a counter to output files that already exist or are written by an earlier job
+fn number_collisions(jobs: &mut [Job]) {
+    let mut taken = HashSet::new();
+    let mut unique = |path: &Path| {
+        let unique = unique_path(path, |path| taken.contains(path) || path.exists());
+        taken.insert(unique.clone());
+        unique
+    };
+    for job in jobs {
+        match job.variants.first() {
+            None => job.out_file = unique(&job.out_file),
+            Some(_) => {
+                for (_, out_file) in &mut job.variants {
+                    *out_file = unique(out_file);
+                }
+                job.out_file = job.variants[0].1.clone();
+            }
+        }
+    }
+}
+
+/// Outputs written next to the originals might land on another input of the batch, e.g.
+/// photo.png converted to photo.jpg. They get the same folder prefix and a counter if that's
+/// taken as well, so no original is overwritten before it's read
+fn avoid_inputs(jobs: &mut [Job]) {
+    let inputs = jobs
+        .iter()
+        .map(|job| canonical_path(&job.in_file))
+        .collect::<HashSet<_>>();
+    let mut taken = jobs
+        .iter()
+        .flat_map(Job::out_files)
+        .map(canonical_path)
+        .collect::<HashSet<_>>();
+    taken.extend(inputs.iter().cloned());
+    let mut unique = |path: &Path| {
+        if !inputs.contains(&canonical_path(path)) {
+            return path.to_path_buf();
+        }
+        let mut name = OsString::from(SAME_DIR_PREFIX);
+        name.push(path.file_name().unwrap_or_default());
+        let unique = unique_path(&path.with_file_name(name), |path| {
+            taken.contains(&canonical_path(path))
+        });
+        taken.insert(canonical_path(&unique));
+        unique
+    };
+    for job in jobs {
+        match job.variants.first() {
+            None => job.out_file = unique(&job.out_file),
+            Some(_) => {
+                for (_, out_file) in &mut job.variants {
+                    *out_file = unique(out_file);
+                }
+                job.out_file = job.variants[0].1.clone();
+            }
+        }
+    }
+}
+
+/// Converted originals get another extension and might end up on another input of the batch,
+/// which is never overwritten. With `avoid_existing` other files aren't overwritten either.
+/// Backups get a counter if originals from different folders share a name or one already exists
+fn unique_in_place_paths(jobs: &mut [Job], avoid_existing: bool) {
+    let mut taken = jobs
+        .iter()
+        .map(|job| job.in_file.clone())
+        .collect::<HashSet<_>>();
+    let mut backups = HashSet::new();
+    for job in jobs {
+        if job.out_file != job.in_file {
+            let unique = unique_path(&job.out_file, |path| {
+                taken.contains(path) || (avoid_existing && path.exists())
+            });
+            taken.insert(unique.clone());
+            job.out_file = unique;
+        }
+        if let Some(backup_file) = &job.backup_file {
+            let unique = unique_path(backup_file, |path| backups.contains(path) || path.exists());
+            backups.insert(unique.clone());
+            job.backup_file = Some(unique);
+        }
+    }
+}
+
+/// `path` with `-1`, `-2`, ... appended to its stem until `is_taken` returns false
+fn unique_path<F>(path: &Path, is_taken: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut unique = path.to_path_buf();
+    let mut counter = 1;
+    while is_taken(&unique) {
+        let mut name = path.file_stem().unwrap_or_default().to_owned();
+        name.push(format!("-{counter}"));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        unique = path.with_file_name(name);
+        counter += 1;
+    }
+    unique
+}
+
+fn parent_dir(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(parent) => parent.to_path_buf(),
+        None => PathBuf::new(),
+    }
+}
+
+/// `path` with its folder resolved, the file itself doesn't have to exist
+fn canonical_path(path: &Path) -> PathBuf {
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imagemagick::OutputFormat;
+
+    #[test]
+    fn converted_outputs_dont_overwrite_other_inputs() {
+        let options = Options {
+            output_folder_name: String::new(),
+            format: FormatOptions {
+                format: OutputFormat::Jpeg,
+                ..FormatOptions::default()
+            },
+            ..Options::default()
+        };
+        let in_files = ["photos/photo.png", "photos/photo.jpg"];
+        let pipeline = Pipeline::new(in_files, options).unwrap();
+        let out_files = pipeline
+            .jobs()
+            .iter()
+            .map(|job| job.out_file.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            out_files,
+            [
+                PathBuf::from("photos/min-photo-1.jpg"),
+                PathBuf::from("photos/min-photo.jpg")
+            ]
+        );
+    }
+}
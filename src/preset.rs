@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, RshrinkError},
+    filesystem::DEFAULT_OUT_DIR,
+    imagemagick::{FormatOptions, MetadataOptions},
+    pipeline::{Backup, Collision, LargerOutputPolicy, Options, Srcset},
+    utils::Dimensions,
+};
+
+/// Presets by their name, sorted for the dropdown
+pub type Presets = BTreeMap<String, Preset>;
+
+/// Everything that decides how a batch is shrinked, can be saved under a name and shared as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+// Presets exported by an older version miss new fields
+#[serde(default)]
+pub struct Preset {
+    pub dimensions: Dimensions,
+    pub change_dimensions: bool,
+    /// Responsive sizes written instead of `dimensions`
+    pub variants: Vec<Dimensions>,
+    pub srcset: Srcset,
+    pub compression_quality: usize,
+    pub target_size_enabled: bool,
+    pub target_size_kb: u64,
+    pub target_size_resize: bool,
+    pub metrics: bool,
+    pub min_ssim_enabled: bool,
+    pub min_ssim: f64,
+    pub format: FormatOptions,
+    pub metadata: MetadataOptions,
+    pub larger_output: LargerOutputPolicy,
+    pub output_folder_name: String,
+    pub output_folder_parent_dir_path: Option<String>,
+    pub output_folder_parent_dir_path_enabled: bool,
+    /// Output file name template, the default name if empty
+    pub file_name_template: String,
+    pub collision: Collision,
+    pub replace_originals: bool,
+    pub backup: Backup,
+    pub backup_dir: Option<String>,
+    /// Skip files that didn't change since the last run with the same settings
+    pub incremental: bool,
+}
+
+impl Preset {
+    pub fn options(&self) -> Options {
+        Options {
+            dimensions: match self.change_dimensions {
+                true => Some(self.dimensions.clone()),
+                false => None,
+            },
+            variants: self.variants.clone(),
+            srcset: self.srcset,
+            compression_quality: self.compression_quality,
+            apply_gaussian_blur: false,
+            target_size: match self.target_size_enabled {
+                true => Some(self.target_size_kb.saturating_mul(1024)),
+                false => None,
+            },
+            target_size_resize: self.target_size_resize,
+            metrics: self.metrics,
+            min_ssim: match self.min_ssim_enabled {
+                true => Some(self.min_ssim),
+                false => None,
+            },
+            format: self.format.clone(),
+            metadata: self.metadata.clone(),
+            larger_output: self.larger_output,
+            output_folder_name: self.output_folder_name.clone(),
+            output_folder_parent_dir: self.output_folder_parent_dir_path.as_ref().map(Into::into),
+            file_name_template: match self.file_name_template.trim().is_empty() {
+                true => None,
+                false => Some(self.file_name_template.clone()),
+            },
+            collision: self.collision,
+            preset_name: None,
+            replace_originals: self.replace_originals,
+            backup: self.backup,
+            backup_dir: self.backup_dir.as_ref().map(Into::into),
+            incremental: self.incremental,
+        }
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            dimensions: Dimensions::default(),
+            change_dimensions: true,
+            variants: Vec::new(),
+            srcset: Srcset::default(),
+            compression_quality: 85,
+            target_size_enabled: false,
+            target_size_kb: 200,
+            target_size_resize: false,
+            metrics: false,
+            min_ssim_enabled: false,
+            min_ssim: 0.98,
+            format: FormatOptions::default(),
+            metadata: MetadataOptions::default(),
+            larger_output: LargerOutputPolicy::default(),
+            output_folder_name: String::from(DEFAULT_OUT_DIR),
+            output_folder_parent_dir_path: None,
+            output_folder_parent_dir_path_enabled: false,
+            file_name_template: String::new(),
+            collision: Collision::default(),
+            replace_originals: false,
+            backup: Backup::default(),
+            backup_dir: None,
+            incremental: true,
+        }
+    }
+}
+
+/// Write `presets` to a JSON file that can be imported on another machine
+pub fn export_presets(path: &Path, presets: &Presets) -> Result<()> {
+    let json = serde_json::to_string_pretty(presets)?;
+    fs::write(path, json).map_err(|err| RshrinkError::io(path, err))
+}
+
+pub fn import_presets(path: &Path) -> Result<Presets> {
+    let json = fs::read_to_string(path).map_err(|err| RshrinkError::io(path, err))?;
+    Ok(serde_json::from_str(&json)?)
+}
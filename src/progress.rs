@@ -0,0 +1,141 @@
+//! Overall progress of a batch, aggregated from the events its jobs send
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::pipeline::{Event, Outcome};
+
+/// Counts, bytes and timing of a batch, fed with every event the pipeline sends
+#[derive(Debug, Clone)]
+pub struct Progress {
+    started_at: Instant,
+    queued: usize,
+    finished: usize,
+    failed: usize,
+    /// Size of the inputs of finished jobs
+    bytes_in: u64,
+    /// Size of the inputs of finished jobs that have an output
+    bytes_in_shrinked: u64,
+    /// Size of the outputs of finished jobs
+    bytes_out: u64,
+    /// Fraction of the jobs that are running, videos report it as they go
+    running: HashMap<usize, f32>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            queued: 0,
+            finished: 0,
+            failed: 0,
+            bytes_in: 0,
+            bytes_in_shrinked: 0,
+            bytes_out: 0,
+            running: HashMap::new(),
+        }
+    }
+}
+
+impl Progress {
+    /// Time is measured from now on
+    pub fn new() -> Progress {
+        Progress::default()
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        match event {
+            Event::Queued { .. } => self.queued += 1,
+            Event::Started { index } => {
+                self.running.insert(*index, 0.);
+            }
+            Event::Progress { index, fraction } => {
+                self.running.insert(*index, *fraction);
+            }
+            Event::Finished(job_result) => {
+                self.finished += 1;
+                self.running.remove(&job_result.index);
+                if let Outcome::Failed(_) = job_result.outcome {
+                    self.failed += 1;
+                }
+                self.bytes_in += job_result.size;
+                if let Some(new_size) = job_result.new_size() {
+                    self.bytes_in_shrinked += job_result.size;
+                    self.bytes_out += new_size;
+                }
+            }
+        }
+    }
+
+    /// Share of the batch that is done from 0 to 1, running videos count with their progress
+    pub fn fraction(&self) -> f32 {
+        match self.queued {
+            0 => 0.,
+            queued => {
+                let running = self.running.values().sum::<f32>();
+                ((self.finished as f32 + running) / queued as f32).min(1.)
+            }
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn files_per_second(&self) -> f32 {
+        match self.elapsed().as_secs_f32() {
+            seconds if seconds > 0. => self.finished as f32 / seconds,
+            _ => 0.,
+        }
+    }
+
+    /// Input bytes shrinked per second
+    pub fn bytes_per_second(&self) -> f32 {
+        match self.elapsed().as_secs_f32() {
+            seconds if seconds > 0. => self.bytes_in as f32 / seconds,
+            _ => 0.,
+        }
+    }
+
+    /// Time left if the remaining jobs take as long as the finished ones, `None` until there is
+    /// anything to extrapolate from
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        match fraction > 0. {
+            true => Some(self.elapsed().mul_f32((1. - fraction) / fraction)),
+            false => None,
+        }
+    }
+
+    /// One line summary like "12 of 40 files, 530 Kb saved, 3.2 files/s, 840 Kb/s, ETA 0:42"
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} files{}, {} Kb saved, {:.1} files/s, {} Kb/s, ETA {}",
+            self.finished,
+            self.queued,
+            match self.failed {
+                0 => String::new(),
+                failed => format!(" ({failed} failed)"),
+            },
+            // Failed jobs have no output, only count the ones that wrote something
+            self.bytes_in_shrinked.saturating_sub(self.bytes_out) / 1024,
+            self.files_per_second(),
+            (self.bytes_per_second() / 1024.) as u64,
+            match self.eta() {
+                Some(eta) => format_duration(eta),
+                None => String::from("-"),
+            }
+        )
+    }
+}
+
+/// Minutes and seconds like 1:05, hours are added if needed
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{hours}:{:02}:{:02}", seconds % 3600 / 60, seconds % 60),
+    }
+}
@@ -0,0 +1,388 @@
+//! Per-file results and totals of a run, exported to share the savings without a screenshot
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::{Result, RshrinkError},
+    imagemagick::Similarity,
+    pipeline::{JobResult, LargerOutputPolicy, Outcome, Shrinked},
+    template::civil_date,
+    utils::round_percent,
+};
+
+/// File formats a report can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    #[default]
+    Json,
+    /// Standalone page with a table, for sharing
+    Html,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 3] = [ReportFormat::Csv, ReportFormat::Json, ReportFormat::Html];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "CSV",
+            ReportFormat::Json => "JSON",
+            ReportFormat::Html => "HTML",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// Format matching the extension of `path`, if it's a known one
+    pub fn from_path(path: &Path) -> Option<ReportFormat> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> std::result::Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!(
+                "Unknown report format {format}, expected csv, json or html"
+            )),
+        }
+    }
+}
+
+/// What happened to a file, more detailed than `Outcome`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Done,
+    /// The result was larger, the original was copied instead
+    OriginalKept,
+    /// The result was larger and not written
+    Skipped,
+    /// Retried with a lower quality because the first result was larger
+    Retried,
+    UpToDate,
+    /// The output file already existed and was left alone
+    Exists,
+    Failed,
+    Cancelled,
+    /// Not shrinked yet
+    Pending,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Done => "done",
+            Status::OriginalKept => "original kept",
+            Status::Skipped => "skipped",
+            Status::Retried => "retried",
+            Status::UpToDate => "up to date",
+            Status::Exists => "exists",
+            Status::Failed => "failed",
+            Status::Cancelled => "cancelled",
+            Status::Pending => "pending",
+        }
+    }
+
+    /// Status of a shrinked file, depending on the larger output policy that was applied
+    pub fn shrinked(larger_output: Option<LargerOutputPolicy>) -> Status {
+        match larger_output {
+            Some(LargerOutputPolicy::CopyOriginal) => Status::OriginalKept,
+            Some(LargerOutputPolicy::Skip) => Status::Skipped,
+            Some(LargerOutputPolicy::Retry) => Status::Retried,
+            None => Status::Done,
+        }
+    }
+}
+
+/// A single row of a report
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub in_file: PathBuf,
+    pub out_file: Option<PathBuf>,
+    pub status: Status,
+    pub size: u64,
+    /// `None` if the file has no result, files whose output already existed count with their
+    /// original size like in the UI's totals
+    pub new_size: Option<u64>,
+    pub original_dimensions: Option<(usize, usize)>,
+    pub dimensions: Option<(usize, usize)>,
+    pub quality: Option<usize>,
+    pub similarity: Option<Similarity>,
+    /// Time spent on the file
+    pub seconds: f64,
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    pub fn new(job_result: &JobResult) -> FileReport {
+        let mut file = FileReport {
+            in_file: job_result.in_file.clone(),
+            out_file: None,
+            status: Status::Pending,
+            size: job_result.size,
+            new_size: None,
+            original_dimensions: None,
+            dimensions: None,
+            quality: None,
+            similarity: None,
+            seconds: job_result.duration.as_secs_f64(),
+            error: None,
+        };
+        match &job_result.outcome {
+            Outcome::Done(shrinked) => {
+                file.apply(shrinked, Status::shrinked(shrinked.larger_output))
+            }
+            Outcome::UpToDate(shrinked) => file.apply(shrinked, Status::UpToDate),
+            Outcome::Failed(err) => {
+                file.status = Status::Failed;
+                file.error = Some(err.to_string());
+            }
+            Outcome::Cancelled => file.status = Status::Cancelled,
+            Outcome::Exists => {
+                file.status = Status::Exists;
+                file.out_file = Some(job_result.out_file.clone());
+                file.new_size = Some(job_result.size);
+            }
+        }
+        file
+    }
+
+    fn apply(&mut self, shrinked: &Shrinked, status: Status) {
+        self.status = status;
+        self.out_file = shrinked.out_file.clone();
+        self.new_size = Some(shrinked.new_size);
+        self.original_dimensions = shrinked.original_dimensions;
+        self.dimensions = shrinked.dimensions;
+        self.quality = shrinked.quality;
+        self.similarity = shrinked.similarity;
+    }
+}
+
+/// Sums over all files of a report
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Totals {
+    pub files: usize,
+    /// Files with a new size, `size` and `new_size` only count these
+    pub shrinked: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub size: u64,
+    pub new_size: u64,
+    /// Percentage of `size` that was saved
+    pub saved_percent: f32,
+    pub seconds: f64,
+}
+
+/// Results of a run with a row per file
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// UTC, e.g. 2022-05-01 12:00:00
+    pub created: String,
+    pub totals: Totals,
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    pub fn new(files: Vec<FileReport>) -> Report {
+        let mut totals = Totals {
+            files: files.len(),
+            ..Totals::default()
+        };
+        for file in &files {
+            match file.status {
+                Status::Failed => totals.failed += 1,
+                Status::Cancelled => totals.cancelled += 1,
+                _ => {}
+            }
+            if let Some(new_size) = file.new_size {
+                totals.shrinked += 1;
+                totals.size += file.size;
+                totals.new_size += new_size;
+            }
+            totals.seconds += file.seconds;
+        }
+        totals.saved_percent = match totals.size {
+            0 => 0.,
+            _ => 100. - round_percent(totals.new_size, totals.size),
+        };
+        Report {
+            created: now(),
+            totals,
+            files,
+        }
+    }
+
+    pub fn write(&self, path: &Path, format: ReportFormat) -> Result<()> {
+        let content = match format {
+            ReportFormat::Csv => self.csv(),
+            ReportFormat::Json => serde_json::to_string_pretty(self)?,
+            ReportFormat::Html => self.html(),
+        };
+        fs::write(path, content).map_err(|err| RshrinkError::io(path, err))
+    }
+
+    /// A row per file, then a blank line and the totals with their own header so the file rows
+    /// keep their column types
+    fn csv(&self) -> String {
+        let mut csv = String::from(
+            "in_file,out_file,status,size,new_size,width,height,new_width,new_height,quality,ssim,psnr,seconds,error\n",
+        );
+        for file in &self.files {
+            let row = [
+                file.in_file.display().to_string(),
+                optional(file.out_file.as_ref().map(|out_file| out_file.display())),
+                file.status.name().to_string(),
+                file.size.to_string(),
+                optional(file.new_size),
+                optional(file.original_dimensions.map(|(width, _)| width)),
+                optional(file.original_dimensions.map(|(_, height)| height)),
+                optional(file.dimensions.map(|(width, _)| width)),
+                optional(file.dimensions.map(|(_, height)| height)),
+                optional(file.quality),
+                optional(
+                    file.similarity
+                        .map(|similarity| format!("{:.4}", similarity.ssim)),
+                ),
+                optional(
+                    file.similarity
+                        .map(|similarity| format!("{:.2}", similarity.psnr)),
+                ),
+                format!("{:.3}", file.seconds),
+                file.error.clone().unwrap_or_default(),
+            ];
+            csv_row(&mut csv, &row);
+        }
+        let totals = &self.totals;
+        csv.push_str("\nfiles,shrinked,failed,cancelled,size,new_size,saved_percent,seconds\n");
+        csv_row(
+            &mut csv,
+            &[
+                totals.files.to_string(),
+                totals.shrinked.to_string(),
+                totals.failed.to_string(),
+                totals.cancelled.to_string(),
+                totals.size.to_string(),
+                totals.new_size.to_string(),
+                format!("{:.1}", totals.saved_percent),
+                format!("{:.3}", totals.seconds),
+            ],
+        );
+        csv
+    }
+
+    fn html(&self) -> String {
+        let totals = &self.totals;
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>rshrink report</title>\n\
+             <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             td,th{{padding:4px 8px;border-bottom:1px solid #ddd;text-align:left}}\
+             .failed{{color:#c00}}</style>\n</head>\n<body>\n<h1>rshrink report</h1>\n\
+             <p>{}: {} of {} files shrinked, {} Kb &rarr; {} Kb ({:.1}% saved), {} failed</p>\n<table>\n\
+             <tr><th>File</th><th>Output</th><th>Status</th><th>Size</th><th>New size</th>\
+             <th>Dimensions</th><th>New dimensions</th><th>Quality</th><th>SSIM</th>\
+             <th>Seconds</th><th>Error</th></tr>\n",
+            self.created,
+            totals.shrinked,
+            totals.files,
+            totals.size / 1024,
+            totals.new_size / 1024,
+            totals.saved_percent,
+            totals.failed,
+        );
+        let dimensions = |dimensions: Option<(usize, usize)>| {
+            optional(dimensions.map(|(width, height)| format!("{width}x{height}")))
+        };
+        for file in &self.files {
+            let _ = writeln!(
+                html,
+                "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{} Kb</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td></tr>",
+                match file.status {
+                    Status::Failed => " class=\"failed\"",
+                    _ => "",
+                },
+                escape_html(&file.in_file.display().to_string()),
+                escape_html(&optional(
+                    file.out_file.as_ref().map(|out_file| out_file.display())
+                )),
+                file.status.name(),
+                file.size / 1024,
+                optional(
+                    file.new_size
+                        .map(|new_size| format!("{} Kb", new_size / 1024))
+                ),
+                dimensions(file.original_dimensions),
+                dimensions(file.dimensions),
+                optional(file.quality),
+                optional(
+                    file.similarity
+                        .map(|similarity| format!("{:.3}", similarity.ssim))
+                ),
+                file.seconds,
+                escape_html(file.error.as_deref().unwrap_or_default()),
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+/// Empty if `None`
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Append `fields` as a line, quoting fields that contain separators or quotes
+fn csv_row(csv: &mut String, fields: &[String]) {
+    let fields = fields
+        .iter()
+        .map(|field| match field.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field.clone(),
+        })
+        .collect::<Vec<_>>();
+    csv.push_str(&fields.join(","));
+    csv.push('\n');
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Current time in UTC, formatted like 2022-05-01 12:00:00
+fn now() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    let time = seconds % 86400;
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
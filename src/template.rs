@@ -0,0 +1,175 @@
+//! Output file names like `{stem}-{width}w.{ext}` or `{date}/{name}`
+
+use crate::error::{Result, RshrinkError};
+
+/// Everything that can be used inside braces
+pub const PLACEHOLDERS: [&str; 9] = [
+    "name", "stem", "ext", "width", "height", "quality", "preset", "index", "date",
+];
+
+/// Replace every `{placeholder}` with its value, placeholders without a value are kept as they are
+pub fn render<F>(template: &str, value: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..];
+        match placeholder.find('}') {
+            Some(end) => {
+                match value(&placeholder[..end]) {
+                    Some(value) => rendered.push_str(&value),
+                    None => rendered.push_str(&rest[start..start + end + 2]),
+                }
+                rest = &placeholder[end + 1..];
+            }
+            // Unclosed, kept as it is
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Check that `template` only uses known placeholders and results in a relative file path
+pub fn validate(template: &str) -> Result<()> {
+    let invalid = |reason: String| Err(RshrinkError::InvalidTemplate(reason));
+    if template.trim().is_empty() {
+        return invalid(String::from("Template is empty"));
+    }
+    // Windows separators and drive letters would leave the output folder as well
+    if template.contains('\\') {
+        return invalid(String::from("Use / to separate folders"));
+    }
+    let is_drive = template.len() >= 2
+        && template.as_bytes()[0].is_ascii_alphabetic()
+        && template.as_bytes()[1] == b':';
+    if template.starts_with('/') || is_drive || template.split('/').any(|part| part == "..") {
+        return invalid(String::from("Has to stay inside the output folder"));
+    }
+    // Would write to a folder instead of a file
+    if template.split('/').any(|part| part == ".") || template.ends_with('/') {
+        return invalid(String::from(
+            "Has to end with a file name, without . folders",
+        ));
+    }
+    if template.matches('{').count() != template.matches('}').count() {
+        return invalid(String::from("Unbalanced braces"));
+    }
+    let unknown = template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}'))
+        .map(|(placeholder, _)| placeholder)
+        .find(|placeholder| !PLACEHOLDERS.contains(placeholder));
+    match unknown {
+        Some(placeholder) => invalid(format!(
+            "Unknown placeholder {{{placeholder}}}, expected one of {}",
+            PLACEHOLDERS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Year, month and day of `days` since the unix epoch
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    // Shift the epoch to 0000-03-01, leap days are at the end of the year then
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = match month < 10 {
+        true => month + 3,
+        false => month - 9,
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(placeholder: &str) -> Option<String> {
+        match placeholder {
+            "date" => Some(String::from("2022-05-01")),
+            "stem" => Some(String::from("photo")),
+            "width" => Some(String::from("800")),
+            "ext" => Some(String::from("jpg")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn render_replaces_placeholders() {
+        assert_eq!(
+            render("{date}/{stem}-{width}w.{ext}", value),
+            "2022-05-01/photo-800w.jpg"
+        );
+        assert_eq!(render("no placeholders", value), "no placeholders");
+    }
+
+    #[test]
+    fn render_keeps_unknown_and_unclosed_placeholders() {
+        assert_eq!(render("{stem}-{nope}.{ext}", value), "photo-{nope}.jpg");
+        assert_eq!(render("{stem}-{ext", value), "photo-{ext");
+    }
+
+    #[test]
+    fn validate_accepts_relative_templates() {
+        for template in [
+            "{name}",
+            "{stem}-{width}w.{ext}",
+            "{date}/{name}",
+            "a..b/{name}",
+            ".{name}",
+            "{date}/.{stem}",
+        ] {
+            assert!(validate(template).is_ok(), "{template}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_invalid_templates() {
+        for template in [
+            "",
+            "  ",
+            "/tmp/{name}",
+            "../{name}",
+            "{date}/../../{name}",
+            "..\\{name}",
+            "sub\\{name}",
+            "C:/{name}",
+            "c:{name}",
+            "{date}/",
+            "{stem}/.",
+            "./{name}",
+            "{date}/./{name}",
+            "{stem",
+            "{stem}}",
+            "{nope}.jpg",
+        ] {
+            assert!(
+                matches!(validate(template), Err(RshrinkError::InvalidTemplate(_))),
+                "{template}"
+            );
+        }
+    }
+
+    #[test]
+    fn civil_date_of_days_since_epoch() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(19113), (2022, 5, 1));
+    }
+}
@@ -1,9 +1,86 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::{Result, RshrinkError};
+
+/// Largest width, height or long edge that can be entered
+const MAX_DIMENSION: usize = 8000;
+/// Largest scale in `ResizeMode::Percent`
+const MAX_PERCENT: usize = 400;
+
+/// How images and videos are scaled to `Dimensions`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeMode {
+    /// Fit into width and height, smaller files keep their size
+    #[default]
+    ShrinkOnly,
+    /// Fit into width and height, smaller files are enlarged
+    Fit,
+    /// Cover width and height and crop the overlap, videos are fitted instead
+    Fill,
+    /// Scale to exactly width and height, ignoring the aspect ratio
+    Exact,
+    /// Scale both sides by `percent`
+    Percent,
+    /// Shrink the longer side to `long_edge`
+    LongEdge,
+}
+
+impl ResizeMode {
+    pub const ALL: [ResizeMode; 6] = [
+        ResizeMode::ShrinkOnly,
+        ResizeMode::Fit,
+        ResizeMode::Fill,
+        ResizeMode::Exact,
+        ResizeMode::Percent,
+        ResizeMode::LongEdge,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResizeMode::ShrinkOnly => "Shrink only",
+            ResizeMode::Fit => "Fit",
+            ResizeMode::Fill => "Fill and crop",
+            ResizeMode::Exact => "Exact",
+            ResizeMode::Percent => "Percent",
+            ResizeMode::LongEdge => "Max long edge",
+        }
+    }
+
+    /// Whether the mode uses width and height
+    pub fn uses_dimensions(&self) -> bool {
+        !matches!(self, ResizeMode::Percent | ResizeMode::LongEdge)
+    }
+}
+
+impl std::str::FromStr for ResizeMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> std::result::Result<Self, Self::Err> {
+        match mode.to_lowercase().as_str() {
+            "shrink" => Ok(ResizeMode::ShrinkOnly),
+            "fit" => Ok(ResizeMode::Fit),
+            "fill" => Ok(ResizeMode::Fill),
+            "exact" => Ok(ResizeMode::Exact),
+            "percent" => Ok(ResizeMode::Percent),
+            "long-edge" => Ok(ResizeMode::LongEdge),
+            _ => Err(format!(
+                "Unknown resize mode {mode}, expected shrink, fit, fill, exact, percent or long-edge"
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+// Dimensions stored by an older version miss the resize mode
+#[serde(default)]
 pub struct Dimensions {
     pub width: usize,
     pub height: usize,
+    pub mode: ResizeMode,
+    /// Only used by `ResizeMode::Percent`
+    pub percent: usize,
+    /// Only used by `ResizeMode::LongEdge`
+    pub long_edge: usize,
 }
 
 impl Default for Dimensions {
@@ -11,43 +88,128 @@ impl Default for Dimensions {
         Dimensions {
             width: 1920,
             height: 1080,
+            mode: ResizeMode::default(),
+            percent: 50,
+            long_edge: 1920,
         }
     }
 }
 
 impl Dimensions {
     pub fn new(width: usize, height: usize) -> Dimensions {
-        Dimensions { width, height }
+        Dimensions {
+            width,
+            height,
+            ..Dimensions::default()
+        }
     }
-    pub fn parse_dimensions(dimensions: &str) -> Result<Dimensions, &str> {
+
+    pub fn percent(percent: usize) -> Result<Dimensions> {
+        if !(1..=MAX_PERCENT).contains(&percent) {
+            return Err(RshrinkError::InvalidDimensions(format!(
+                "Percent has to be between 1 and {MAX_PERCENT}, got {percent}"
+            )));
+        }
+        Ok(Dimensions {
+            mode: ResizeMode::Percent,
+            percent,
+            ..Dimensions::default()
+        })
+    }
+
+    pub fn long_edge(long_edge: usize) -> Result<Dimensions> {
+        if !(1..=MAX_DIMENSION).contains(&long_edge) {
+            return Err(RshrinkError::InvalidDimensions(format!(
+                "Long edge has to be between 1 and {MAX_DIMENSION}, got {long_edge}"
+            )));
+        }
+        Ok(Dimensions {
+            mode: ResizeMode::LongEdge,
+            long_edge,
+            ..Dimensions::default()
+        })
+    }
+
+    /// Fit into `width`, the height is only limited by the largest dimension
+    pub fn width(width: usize) -> Result<Dimensions> {
+        if !(1..=MAX_DIMENSION).contains(&width) {
+            return Err(RshrinkError::InvalidDimensions(format!(
+                "Width has to be between 1 and {MAX_DIMENSION}, got {width}"
+            )));
+        }
+        Ok(Dimensions::new(width, MAX_DIMENSION))
+    }
+
+    /// Parse a width or WIDTHxHEIGHT
+    pub fn parse_size(size: &str) -> Result<Dimensions> {
+        match size.contains('x') {
+            true => Dimensions::parse_dimensions(size),
+            false => match size.trim().parse::<usize>() {
+                Ok(width) => Dimensions::width(width),
+                Err(_) => Err(RshrinkError::InvalidDimensions(format!(
+                    "Expected WIDTH or WIDTHxHEIGHT, got {size}"
+                ))),
+            },
+        }
+    }
+
+    /// Size a `width`x`height` image is scaled to, `ResizeMode::Fill` crops it to `self.width`x
+    /// `self.height` afterwards
+    pub fn scaled_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let (width_f, height_f) = (width.max(1) as f64, height.max(1) as f64);
+        let scale = match self.mode {
+            ResizeMode::ShrinkOnly => {
+                f64::min(self.width as f64 / width_f, self.height as f64 / height_f).min(1.)
+            }
+            ResizeMode::Fit => f64::min(self.width as f64 / width_f, self.height as f64 / height_f),
+            ResizeMode::Fill => {
+                f64::max(self.width as f64 / width_f, self.height as f64 / height_f)
+            }
+            ResizeMode::Exact => return (self.width.max(1), self.height.max(1)),
+            ResizeMode::Percent => self.percent as f64 / 100.,
+            ResizeMode::LongEdge => (self.long_edge as f64 / width_f.max(height_f)).min(1.),
+        };
+        (
+            ((width_f * scale).round() as usize).max(1),
+            ((height_f * scale).round() as usize).max(1),
+        )
+    }
+    pub fn parse_dimensions(dimensions: &str) -> Result<Dimensions> {
         let d: Vec<&str> = dimensions.split('x').collect();
         if let [width, height] = d[..] {
-            return Ok(Dimensions {
-                width: width.parse::<usize>().expect("Invalid width!"),
-                height: height.parse::<usize>().expect("Invalid height!"),
-            });
+            let mut parsed = Dimensions::default();
+            parsed.save_dimensions_from_string((width.to_string(), height.to_string()))?;
+            return Ok(parsed);
         }
-        Err("Invalid dimensions!")
+        Err(RshrinkError::InvalidDimensions(format!(
+            "Expected WIDTHxHEIGHT, got {dimensions}"
+        )))
     }
 
     pub fn as_string(&self) -> (String, String) {
         (self.width.to_string(), self.height.to_string())
     }
 
-    pub fn save_dimensions_from_string(
-        &mut self,
-        dimensions: (String, String),
-    ) -> Result<(), &str> {
+    pub fn save_dimensions_from_string(&mut self, dimensions: (String, String)) -> Result<()> {
         let (width, height) = dimensions;
         if let (Ok(width), Ok(height)) = (width.parse::<usize>(), height.parse::<usize>()) {
-            if width > 8000 || height > 8000 {
-                return Err("Dimensions to big!");
+            if width > MAX_DIMENSION || height > MAX_DIMENSION {
+                return Err(RshrinkError::InvalidDimensions(String::from(
+                    "Dimensions to big!",
+                )));
+            }
+            if width == 0 || height == 0 {
+                return Err(RshrinkError::InvalidDimensions(String::from(
+                    "Width and height have to be at least 1",
+                )));
             }
             self.width = width;
             self.height = height;
             return Ok(());
         }
-        Err("Failed to parse dimensions")
+        Err(RshrinkError::InvalidDimensions(format!(
+            "Failed to parse {width}x{height}"
+        )))
     }
 }
 
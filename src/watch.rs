@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    error::Result,
+    filesystem::{filter_files, fingerprint, list_files, list_files_recursive},
+    pipeline::{CancellationToken, Job, Options},
+};
+
+/// How often the watched folder is scanned
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Files still being written are left alone until their size and modification time stayed the
+/// same for this long
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Finds files that appear or change in a folder, files that already exist are left alone
+pub struct Watcher {
+    dir: PathBuf,
+    file_sel: String,
+    recursive: bool,
+    options: Options,
+    /// Files that changed recently with their size, modification time and when that was noticed
+    settling: HashMap<PathBuf, (u64, SystemTime, Instant)>,
+    /// Size and modification time of files that have been handed out or existed from the start
+    known: HashMap<PathBuf, (u64, SystemTime)>,
+    /// Files written by the jobs, they match the file selection as well
+    outputs: HashSet<PathBuf>,
+    /// Number of jobs handed out so far, for the `{index}` placeholder
+    next_index: usize,
+}
+
+impl Watcher {
+    /// Jobs are created with `options` as they are now, later changes don't apply
+    pub fn new(dir: &Path, file_sel: &str, recursive: bool, options: Options) -> Result<Watcher> {
+        let mut watcher = Watcher::empty(dir, file_sel, recursive, options)?;
+        for file in watcher.scan()? {
+            if let Some(fingerprint) = fingerprint(&file) {
+                watcher.known.insert(file, fingerprint);
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Like `new`, but only the inputs of `jobs` are left alone and their outputs are ignored.
+    /// Created before `jobs` run, files added while they're shrinked are picked up afterwards
+    pub fn after_jobs(
+        dir: &Path,
+        file_sel: &str,
+        recursive: bool,
+        options: Options,
+        jobs: &[Job],
+    ) -> Result<Watcher> {
+        let mut watcher = Watcher::empty(dir, file_sel, recursive, options)?;
+        for job in jobs {
+            if let Some(fingerprint) = fingerprint(&job.in_file) {
+                watcher.known.insert(job.in_file.clone(), fingerprint);
+            }
+            watcher.ignore_outputs(job);
+        }
+        watcher.next_index = jobs.len();
+        Ok(watcher)
+    }
+
+    fn empty(dir: &Path, file_sel: &str, recursive: bool, options: Options) -> Result<Watcher> {
+        options.validate()?;
+        Ok(Watcher {
+            dir: dir.to_path_buf(),
+            file_sel: file_sel.to_owned(),
+            recursive,
+            options,
+            settling: HashMap::new(),
+            known: HashMap::new(),
+            outputs: HashSet::new(),
+            next_index: 0,
+        })
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Scan the folder once, returns jobs for files that are new or changed and have settled
+    pub fn poll(&mut self) -> Result<Vec<Job>> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        for file in self.scan()? {
+            let (size, modified) = match fingerprint(&file) {
+                Some(fingerprint) => fingerprint,
+                // Removed again in the meantime
+                None => continue,
+            };
+            if self.outputs.contains(&file) || self.known.get(&file) == Some(&(size, modified)) {
+                continue;
+            }
+            match self.settling.get(&file) {
+                Some(&(settling_size, settling_modified, since))
+                    if (settling_size, settling_modified) == (size, modified) =>
+                {
+                    if now.duration_since(since) >= SETTLE_TIME {
+                        self.settling.remove(&file);
+                        self.known.insert(file.clone(), (size, modified));
+                        ready.push(file);
+                    }
+                }
+                _ => {
+                    self.settling.insert(file, (size, modified, now));
+                }
+            }
+        }
+
+        Ok(ready.into_iter().map(|file| self.job(file)).collect())
+    }
+
+    /// Poll in a background thread until `cancellation_token` is cancelled. `notify` is called
+    /// whenever something has been sent
+    pub fn spawn<F>(
+        mut self,
+        cancellation_token: CancellationToken,
+        notify: F,
+    ) -> Receiver<Result<Vec<Job>>>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            while !cancellation_token.is_cancelled() {
+                thread::sleep(POLL_INTERVAL);
+                let jobs = match self.poll() {
+                    Ok(jobs) if jobs.is_empty() => continue,
+                    jobs => jobs,
+                };
+                // Receiver was dropped, nobody is watching anymore
+                if sender.send(jobs).is_err() {
+                    break;
+                }
+                notify();
+            }
+        });
+        receiver
+    }
+
+    fn scan(&self) -> Result<Vec<PathBuf>> {
+        match self.recursive {
+            true => list_files_recursive(
+                &self.dir,
+                &self.file_sel,
+                self.options.excluded_dir(&self.dir).as_deref(),
+            ),
+            false => {
+                let files = list_files(&self.dir.to_string_lossy())?;
+                Ok(filter_files(files, &self.file_sel)?
+                    .into_iter()
+                    .map(|file| self.dir.join(file))
+                    .collect())
+            }
+        }
+    }
+
+    fn job(&mut self, file: PathBuf) -> Job {
+        let index = self.next_index;
+        self.next_index += 1;
+        let job = match self.recursive {
+            true => Job::in_tree(file, index, &self.dir, &self.options),
+            false => Job::new(file, index, &self.options),
+        };
+        self.ignore_outputs(&job);
+        job
+    }
+
+    /// Files written by `job` match the file selection as well, they're not shrinked again
+    fn ignore_outputs(&mut self, job: &Job) {
+        for out_file in job.out_files() {
+            // A copied original keeps its extension
+            if let Some(extension) = job.in_file.extension() {
+                self.outputs.insert(out_file.with_extension(extension));
+            }
+            self.outputs.insert(out_file.to_path_buf());
+        }
+    }
+}